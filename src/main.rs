@@ -1,14 +1,18 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs::{create_dir_all, File};
+use std::io::{ErrorKind, IsTerminal, Write};
 use std::iter::Sum;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use chrono::prelude::*;
-use clap::{Parser, Subcommand};
+use clap::{ArgEnum, Parser, Subcommand};
 use directories::ProjectDirs;
 use humantime::format_duration;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /*
@@ -25,10 +29,71 @@ use serde::{Deserialize, Serialize};
 #[derive(Parser)]
 #[clap(author, version, about)]
 struct Opts {
+    #[clap(
+        long,
+        global = true,
+        conflicts_with = "color",
+        help = "Don't use colors in the output, same as --color never"
+    )]
+    no_color: bool,
+
+    #[clap(
+        long,
+        global = true,
+        arg_enum,
+        default_value = "auto",
+        help = "When to use colors, auto uses them on terminals or with CLICOLOR_FORCE set"
+    )]
+    color: ColorChoice,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Write the state file indented instead of compact, e.g. for diffs in version control"
+    )]
+    pretty_state: bool,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Warn about timers with overlapping records before running the command"
+    )]
+    warn_overlap: bool,
+
     #[clap(subcommand)]
     subcmd: SubCommand,
 }
 
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Decides whether to color the output. With `auto` a `CLICOLOR_FORCE`
+/// other than "0" turns colors on and a non-empty `NO_COLOR` turns them off,
+/// otherwise they are used if stdout is a terminal.
+fn should_colorize(
+    choice: ColorChoice,
+    env: impl Fn(&str) -> Option<String>,
+    stdout_is_terminal: bool,
+) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            if env("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+                true
+            } else if env("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+                false
+            } else {
+                stdout_is_terminal
+            }
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum SubCommand {
     #[clap(about = "Starts the timer")]
@@ -38,9 +103,119 @@ enum SubCommand {
     #[clap(about = "Abort the current timer")]
     Abort,
     #[clap(about = "Shows the current total time")]
-    Show,
+    Show(ShowCommand),
+    #[clap(about = "Lists all timers")]
+    List(ListCommand),
+    #[clap(about = "Sets the description of a timer")]
+    Describe(DescribeCommand),
     #[clap(about = "Resets the total time")]
-    Reset,
+    Reset(ResetCommand),
+    #[clap(about = "Sets the hourly rate of a timer")]
+    SetRate(SetRateCommand),
+    #[clap(about = "Edits a record of a timer")]
+    Edit(EditCommand),
+    #[clap(about = "Checks the stored data for inconsistencies")]
+    Check,
+    #[clap(about = "Imports records from a file")]
+    Import(ImportCommand),
+    #[clap(about = "Prints a compact indicator of the running timer for shell prompts")]
+    Prompt(PromptCommand),
+    #[clap(about = "Lists the records of a timer")]
+    Records(RecordsCommand),
+    #[clap(about = "Shows the tracked time per day")]
+    Report(ReportCommand),
+    #[clap(about = "Exports the records of a timer")]
+    Export(ExportCommand),
+    #[clap(about = "Shows a calendar of the tracked time per day")]
+    Heatmap(HeatmapCommand),
+    #[clap(about = "Searches the comments of all records")]
+    Search(SearchCommand),
+    #[clap(about = "Exits successfully if a timer is running, for use in scripts")]
+    Running,
+    #[clap(about = "Shows the running timer")]
+    Status(StatusCommand),
+    #[clap(about = "Generates an invoice for a month")]
+    Invoice(InvoiceCommand),
+    #[clap(about = "Shows the record stopped most recently")]
+    Last(LastCommand),
+    #[clap(about = "Shows the record started earliest")]
+    First(FirstCommand),
+    #[clap(about = "Shows statistics of the record durations of a timer")]
+    Stats(StatsCommand),
+    #[clap(about = "Calculates the duration between two times")]
+    Duration(DurationCommand),
+    #[clap(about = "Prints the JSON schema of the state file or exported records")]
+    Schema(SchemaCommand),
+    #[clap(about = "Prints the paths and settings in effect, for debugging")]
+    Env(EnvCommand),
+    #[clap(about = "Shows the tracked time of all timers combined")]
+    Total(TotalCommand),
+    #[clap(about = "Merges records of a timer that are only a short break apart")]
+    Coalesce(CoalesceCommand),
+    #[clap(about = "Shows the tracked time of the current week")]
+    Week(WeekCommand),
+    #[clap(about = "Splits a record of a timer into two")]
+    Split(SplitCommand),
+    #[clap(about = "Sets the color a timer is shown in")]
+    SetColor(SetColorCommand),
+    #[clap(about = "Sets the time a timer should reach each week")]
+    SetGoal(SetGoalCommand),
+    #[clap(about = "Keeps a timer at the top of listings")]
+    Pin(PinCommand),
+    #[clap(about = "Lists a pinned timer in its usual place again")]
+    Unpin(PinCommand),
+    #[clap(about = "Defines a short name for a timer")]
+    Alias(AliasCommand),
+    #[clap(about = "Writes a timestamped copy of the state")]
+    Backup(BackupCommand),
+    #[clap(about = "Replaces the state with a backup")]
+    Restore(RestoreCommand),
+    #[clap(about = "Corrects the total of a timer without adding a record")]
+    Adjust(AdjustCommand),
+    #[clap(about = "Renames a tag in the records of all timers")]
+    RenameTag(RenameTagCommand),
+    #[clap(about = "Removes a tag from the records of all timers")]
+    RemoveTag(RemoveTagCommand),
+    #[clap(about = "Shows the tracked time per tag")]
+    Tags(TagsCommand),
+}
+
+impl SubCommand {
+    /// Whether this command only reads the state, so it doesn't need to be written back
+    fn is_read_only(&self) -> bool {
+        if let SubCommand::Report(cmd) = self {
+            return !cmd.mark;
+        }
+        // listing or looking up aliases doesn't change them
+        if let SubCommand::Alias(cmd) = self {
+            return cmd.timer_name.is_none() && !cmd.remove;
+        }
+
+        matches!(
+            self,
+            SubCommand::Show(_)
+                | SubCommand::List(_)
+                | SubCommand::Check
+                | SubCommand::Prompt(_)
+                | SubCommand::Records(_)
+                | SubCommand::Export(_)
+                | SubCommand::Heatmap(_)
+                | SubCommand::Search(_)
+                | SubCommand::Running
+                | SubCommand::Status(_)
+                | SubCommand::Invoice(_)
+                | SubCommand::Env(_)
+                | SubCommand::Duration(_)
+                | SubCommand::Last(_)
+                | SubCommand::First(_)
+                | SubCommand::Stats(_)
+                | SubCommand::Schema(_)
+                | SubCommand::Total(_)
+                | SubCommand::Week(_)
+                | SubCommand::Backup(_)
+                | SubCommand::Tags(_)
+        )
+    }
 }
 
 #[derive(Parser)]
@@ -50,301 +225,9857 @@ struct StartCommand {
 
     #[clap(long, short, help = "Create timer with this name")]
     create: bool,
+
+    #[clap(long, help = "Never create a timer, even if --create is given")]
+    no_create: bool,
+
+    #[clap(long, help = "A comment for the record, can be changed when stopping")]
+    comment: Option<String>,
+
+    #[clap(
+        long,
+        short,
+        help = "Stop the running timer first instead of failing, even if it is the same timer. \
+                The stopped record gets the --comment, or none."
+    )]
+    force: bool,
 }
 
 #[derive(Parser)]
 struct StopCommand {
-    #[clap(help = "Timer to stop")]
+    #[clap(help = "Timer to stop, fails unless it is the running timer")]
     timer_name: Option<String>,
 
     #[clap(
         long,
         help = "Stop time to use instead of now (if you forgot to stop your timer again)"
     )]
-    stop_time: String,
+    stop_time: Option<String>,
 
-    #[clap(long, help = "A comment to add to this timer record")]
-    comment: String,
-}
+    #[clap(
+        long,
+        help = "A comment to add to this timer record, replacing the one given on start"
+    )]
+    comment: Option<String>,
 
-#[derive(Debug, PartialEq)]
-enum AppError {
-    TimerAlreadyRunning,
-    NoTimerRunning,
-    NoSuchTimer,
-}
+    #[clap(
+        long,
+        conflicts_with = "comment",
+        help = "Read the comment for this timer record from a file"
+    )]
+    comment_from_file: Option<PathBuf>,
 
-impl Error for AppError {}
+    #[clap(
+        long,
+        help = "Text to append to the comment given on start (or to --comment if both are given)"
+    )]
+    append_comment: Option<String>,
 
-impl std::fmt::Display for AppError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let string = match self {
-            AppError::TimerAlreadyRunning => "Timer already running",
-            AppError::NoTimerRunning => "No timer running",
-            AppError::NoSuchTimer => "No timer with this name",
-        };
+    #[clap(
+        long,
+        help = "Comment to use if none is given, with {date}, {timer} and {duration} filled in"
+    )]
+    comment_template: Option<String>,
 
-        f.write_str(string)
-    }
-}
+    #[clap(long, help = "Fail if the record would have no comment")]
+    comment_required: bool,
 
-#[derive(Deserialize, Serialize)]
-struct TimerRecord {
-    start: DateTime<Utc>,
-    end: DateTime<Utc>,
-    comment: String,
-}
-impl TimerRecord {
-    fn new(start: DateTime<Utc>, end: DateTime<Utc>, comment: String) -> Self {
-        Self {
-            start,
-            end,
-            comment,
-        }
-    }
+    #[clap(
+        long,
+        parse(try_from_str = parse_rate),
+        help = "Hourly rate for this record instead of the timer's rate"
+    )]
+    rate: Option<f64>,
 
-    fn duration(&self) -> Duration {
-        // in case start > end date, return 0s duration
-        (self.end - self.start).to_std().unwrap_or_default()
-    }
-}
+    #[clap(
+        long,
+        parse(try_from_str = humantime::parse_duration),
+        help = "Discard the record instead if it would be shorter than this, e.g. 1min"
+    )]
+    discard_if_shorter: Option<Duration>,
 
-#[derive(Deserialize, Serialize)]
-struct Timer {
-    records: Vec<TimerRecord>,
-    current_start: Option<DateTime<Utc>>,
-}
+    #[clap(long, conflicts_with = "timer-name", help = "Stop every running timer")]
+    all: bool,
 
-impl Default for Timer {
-    fn default() -> Self {
-        Self {
-            records: vec![],
-            current_start: None,
-        }
-    }
+    #[clap(
+        long,
+        conflicts_with = "discard-if-shorter",
+        help = "Record the time so far but keep the timer running from the stop time on"
+    )]
+    keep_running: bool,
 }
 
-impl Timer {
-    fn start_timer(&mut self, start_time: DateTime<Utc>) -> Result<(), AppError> {
-        if self.current_start.is_some() {
-            return Err(AppError::TimerAlreadyRunning);
-        }
+#[derive(Parser)]
+struct ShowCommand {
+    #[clap(help = "Timer to show (defaults to the active timer)")]
+    timer_name: Option<String>,
 
-        self.current_start = Some(start_time);
+    #[clap(long, help = "Print as JSON")]
+    json: bool,
 
-        Ok(())
-    }
+    #[clap(long, help = "Print as indented JSON")]
+    json_pretty: bool,
 
-    fn stop_timer(
-        &mut self,
-        stop_time: DateTime<Utc>,
-        comment: String,
-    ) -> Result<&TimerRecord, AppError> {
-        if let Some(current_start) = self.current_start {
-            self.records
-                .push(TimerRecord::new(current_start, stop_time, comment));
-            let record = self.records.last().unwrap();
+    #[clap(long, arg_enum, default_value = "2dp", help = "How to round the cost")]
+    round_currency: CurrencyRounding,
 
-            self.current_start = None;
+    #[clap(long, arg_enum, help = "Separators of the cost, e.g. de for 1.234,50")]
+    locale: Option<NumberLocale>,
 
-            Ok(record)
-        } else {
-            Err(AppError::NoTimerRunning)
-        }
-    }
+    #[clap(long, help = "Show a chart of the daily totals of the last days")]
+    chart: bool,
 
-    fn total_duration(&self) -> Duration {
-        let durations = self.records.iter().map(|record| record.duration());
-        Duration::sum(durations)
-    }
+    #[clap(long, default_value = "14", help = "Number of days shown by --chart")]
+    days: u32,
 
-    fn is_running(&self) -> bool {
-        self.current_start.is_some()
-    }
-}
+    #[clap(
+        long,
+        help = "Show at most this many units of durations, e.g. 2 for \"2days 3h\""
+    )]
+    max_units: Option<usize>,
 
-#[derive(Deserialize, Serialize)]
-struct AppState {
-    timers: HashMap<String, Timer>,
-    active_timer: Option<String>,
-}
-impl Default for AppState {
-    fn default() -> Self {
-        AppState {
-            timers: HashMap::default(),
-            active_timer: None,
-        }
-    }
+    #[clap(
+        long,
+        conflicts_with_all = &["json", "json-pretty", "duration-only"],
+        help = "Print the total including the running interval on one line"
+    )]
+    short: bool,
+
+    #[clap(
+        long,
+        help = "Show the progress towards the weekly goal, if the timer has one"
+    )]
+    goal: bool,
+
+    #[clap(long, help = "List this many of the latest records")]
+    recent: Option<usize>,
+
+    #[clap(
+        long,
+        requires = "recent",
+        help = "List the recent records newest first"
+    )]
+    reverse: bool,
+
+    #[clap(
+        long,
+        conflicts_with_all = &["json", "json-pretty"],
+        help = "Only print the total in seconds, including the running interval"
+    )]
+    duration_only: bool,
+
+    #[clap(
+        long,
+        requires = "duration-only",
+        help = "Leave the running interval out of --duration-only"
+    )]
+    exclude_running: bool,
 }
 
-impl AppState {
-    fn get_active_timer(&self) -> Option<&Timer> {
-        match &self.active_timer {
-            Some(timer_name) => self.timers.get(timer_name),
-            None => None,
-        }
-    }
+#[derive(Parser)]
+struct ListCommand {
+    #[clap(long, help = "Print as JSON")]
+    json: bool,
 
-    fn has_active_timer(&self) -> bool {
-        if let Some(timer_name) = &self.active_timer {
-            self.timers.contains_key(timer_name)
-        } else {
-            false
-        }
-    }
+    #[clap(long, help = "Print as indented JSON")]
+    json_pretty: bool,
 
-    fn set_timer_active(&mut self, timer_name: &str) -> Result<(), AppError> {
-        if self.timers.contains_key(timer_name) {
-            self.active_timer = Some(String::from(timer_name));
+    #[clap(long, arg_enum, default_value = "name", help = "Order of the timers")]
+    sort: ListSort,
 
-            Ok(())
-        } else {
-            Err(AppError::NoSuchTimer)
-        }
-    }
+    #[clap(long, help = "Reverse the order")]
+    reverse: bool,
 
-    fn create_timer(&mut self, name: &str) -> Option<&Timer> {
-        if self.timers.contains_key(name) {
-            None
-        } else {
-            let timer = Timer::default();
-            self.timers.insert(name.to_string(), timer);
+    #[clap(long, help = "Only list running timers")]
+    only_running: bool,
 
-            self.get_timer(name)
-        }
-    }
+    #[clap(long, help = "Also list timers without any records")]
+    all: bool,
+}
 
-    fn get_timer(&self, name: &str) -> Option<&Timer> {
-        self.timers.get(name)
-    }
+#[derive(ArgEnum, Clone, Copy)]
+enum ListSort {
+    /// Alphabetically
+    Name,
+    /// Most tracked time first
+    Total,
+    /// Most records first
+    Records,
+    /// Most recently used first
+    Recent,
+}
 
-    fn read_from_file(path: &Path) -> Result<Self, serde_json::Error> {
-        let file = File::open(path);
+#[derive(Parser)]
+struct SplitCommand {
+    #[clap(help = "Timer of the record")]
+    timer_name: String,
 
-        if let Ok(file) = file {
-            serde_json::from_reader(file)
-        } else {
-            Ok(AppState::default())
-        }
-    }
+    #[clap(help = "Index of the record, starting at 0")]
+    index: usize,
 
-    fn write_to_file(&self, path: &Path) -> Result<(), serde_json::Error> {
-        let file = File::create(path).unwrap();
+    #[clap(help = "Time to split the record at")]
+    at: String,
 
-        serde_json::to_writer(file, self)
-    }
+    #[clap(long, help = "New comment for the part before the split")]
+    comment_before: Option<String>,
+
+    #[clap(long, help = "New comment for the part after the split")]
+    comment_after: Option<String>,
 }
 
-fn get_statefile_path() -> std::path::PathBuf {
-    let dirs = ProjectDirs::from("eu", "markuskasten", "mtt").unwrap();
-    let state_filename = "state.json";
+#[derive(Parser)]
+struct ResetCommand {
+    #[clap(
+        long,
+        help = "Remove the records of all timers instead of just the active one"
+    )]
+    all: bool,
 
-    create_dir_all(&dirs.data_dir()).unwrap();
+    #[clap(long, short, help = "Reset all timers without asking")]
+    yes: bool,
 
-    let state_path = dirs.data_dir().join(state_filename);
+    #[clap(
+        long,
+        help = "Keep the timers with their rates and descriptions, only remove records (default)"
+    )]
+    keep_timers: bool,
 
-    state_path
+    #[clap(
+        long,
+        conflicts_with = "keep-timers",
+        help = "Remove the timers themselves, too"
+    )]
+    delete_timers: bool,
 }
 
-fn get_duration_string(duration: &Duration) -> String {
-    let duration_secs = Duration::from_secs(duration.as_secs());
+#[derive(Parser)]
+struct AdjustCommand {
+    #[clap(help = "Timer to adjust")]
+    timer_name: String,
 
-    let formatted = format_duration(duration_secs);
+    #[clap(
+        allow_hyphen_values = true,
+        parse(try_from_str = parse_signed_duration),
+        help = "Time to add to the total, negative to take it away, e.g. -30m"
+    )]
+    amount: i64,
 
-    formatted.to_string()
+    #[clap(long, help = "Why the total needed correcting")]
+    comment: Option<String>,
 }
 
-fn main() {
-    let opts = Opts::parse();
+#[derive(Parser)]
+struct RenameTagCommand {
+    #[clap(help = "Tag to rename")]
+    old: String,
 
-    let state_path = get_statefile_path();
-    let mut state = AppState::read_from_file(&state_path).unwrap_or_default();
+    #[clap(help = "New name of the tag, merged with it where records already have it")]
+    new: String,
+}
 
-    match opts.subcmd {
-        SubCommand::Start(_cmd) => {}
-        SubCommand::Stop(_cmd) => {}
-        SubCommand::Abort => {}
-        SubCommand::Show => {}
-        SubCommand::Reset => {}
-    };
+#[derive(Parser)]
+struct RemoveTagCommand {
+    #[clap(help = "Tag to remove")]
+    tag: String,
 
-    state.write_to_file(&state_path).unwrap();
+    #[clap(long, help = "Only remove it from the records of this timer")]
+    timer: Option<String>,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[derive(Parser)]
+struct TagsCommand {
+    #[clap(
+        long,
+        help = "Only count the records of this timer, can be given several times"
+    )]
+    timer: Vec<String>,
+}
 
-    #[test]
-    fn test_timerrecord_duration() {
-        let duration = Duration::from_secs(1234);
-        let start = Utc::now();
-        let end = start + chrono::Duration::from_std(duration).unwrap();
+#[derive(Parser)]
+struct BackupCommand {
+    #[clap(help = "Directory to write the backup to (defaults to \"backups\" in the data dir)")]
+    dir: Option<PathBuf>,
+}
 
-        let record = TimerRecord::new(start, end, "".to_owned());
+#[derive(Parser)]
+struct RestoreCommand {
+    #[clap(help = "Backup file to restore")]
+    path: PathBuf,
 
-        assert_eq!(record.duration(), duration);
+    #[clap(long, short, help = "Restore without asking")]
+    yes: bool,
+}
 
-        // zero duration
-        let record = TimerRecord::new(start, start, "".to_owned());
+#[derive(Parser)]
+struct SetGoalCommand {
+    #[clap(help = "Timer to set the goal of")]
+    timer_name: String,
 
-        assert_eq!(record.duration(), Duration::ZERO);
-    }
+    #[clap(
+        parse(try_from_str = humantime::parse_duration),
+        help = "Time to track per week, e.g. 20h (removes the goal if omitted)"
+    )]
+    weekly_goal: Option<Duration>,
+}
 
-    #[test]
-    fn test_timer_total_duration() {
-        let duration = Duration::from_secs(1234);
-        let start = Utc::now();
-        let end = start + chrono::Duration::from_std(duration).unwrap();
-        let record = TimerRecord::new(start, end, "".to_owned());
+#[derive(Parser)]
+struct SetColorCommand {
+    #[clap(help = "Timer to set the color of")]
+    timer_name: String,
+
+    #[clap(
+        help = "Color name like \"blue\" or hex code like \"#3366ff\" (goes back to the automatic color if omitted)"
+    )]
+    color: Option<String>,
+}
+
+#[derive(Parser)]
+struct AliasCommand {
+    #[clap(help = "Short name to define (lists all aliases if omitted)")]
+    alias: Option<String>,
+
+    #[clap(help = "Timer the alias stands for")]
+    timer_name: Option<String>,
+
+    #[clap(long, conflicts_with = "timer-name", help = "Remove the alias")]
+    remove: bool,
+}
+
+#[derive(Parser)]
+struct PinCommand {
+    #[clap(help = "Timer to pin or unpin")]
+    timer_name: String,
+}
+
+#[derive(Parser)]
+struct DescribeCommand {
+    #[clap(help = "Timer to describe")]
+    timer_name: String,
+
+    #[clap(help = "Description, e.g. client or ticket URL (removes the description if omitted)")]
+    description: Option<String>,
+}
+
+#[derive(Parser)]
+struct SetRateCommand {
+    #[clap(help = "Timer to set the rate for")]
+    timer_name: String,
+
+    #[clap(parse(try_from_str = parse_rate), help = "Hourly rate")]
+    rate: f64,
+}
+
+#[derive(Parser)]
+struct EditCommand {
+    #[clap(help = "Timer of the record")]
+    timer_name: String,
+
+    #[clap(help = "Index of the record, starting at 0")]
+    index: usize,
+
+    #[clap(long, help = "New comment for the record")]
+    comment: Option<String>,
+
+    #[clap(
+        long,
+        parse(try_from_str = parse_rate),
+        help = "Hourly rate for this record instead of the timer's rate"
+    )]
+    rate: Option<f64>,
+
+    #[clap(
+        long,
+        conflicts_with = "rate",
+        help = "Use the timer's rate for this record"
+    )]
+    clear_rate: bool,
+
+    #[clap(long, help = "Add this tag to the record, can be given several times")]
+    add_tag: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Remove this tag from the record, can be given several times"
+    )]
+    remove_tag: Vec<String>,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum ImportFormat {
+    Csv,
+}
+
+#[derive(Parser)]
+struct ImportCommand {
+    #[clap(help = "File to import")]
+    path: PathBuf,
+
+    #[clap(long, arg_enum, default_value = "csv", help = "Format of the file")]
+    format: ImportFormat,
+
+    #[clap(
+        long,
+        help = "Timer to import into, for files without a timer column (defaults to the active timer)"
+    )]
+    timer: Option<String>,
+}
+
+#[derive(Parser)]
+struct PromptCommand {
+    #[clap(
+        long,
+        help = "Format of the indicator, {name} and {elapsed} are replaced [default: \" {name}⏱{elapsed}\"]"
+    )]
+    format: Option<String>,
+}
+
+#[derive(Parser)]
+struct StatusCommand {
+    #[clap(
+        long,
+        help = "Only print the seconds the timer is running for, 0 if none is running"
+    )]
+    running_duration: bool,
+
+    #[clap(
+        long,
+        default_value = "40",
+        help = "Shorten the comment of the running timer to this many characters"
+    )]
+    comment_width: usize,
+
+    #[clap(
+        long,
+        conflicts_with = "running-duration",
+        help = "Also print how many records the active timer has"
+    )]
+    record_count: bool,
+}
+
+#[derive(Parser)]
+struct RangeOptions {
+    #[clap(long, help = "Only include records starting at or after this time")]
+    since: Option<String>,
+
+    #[clap(long, help = "Only include records starting before this time")]
+    until: Option<String>,
+
+    #[clap(
+        long,
+        arg_enum,
+        conflicts_with_all = &["since", "until"],
+        help = "Only include records starting in this local day, week or month"
+    )]
+    range: Option<RangeKeyword>,
+
+    #[clap(
+        long,
+        parse(try_from_str = humantime::parse_duration),
+        help = "Leave out records shorter than this, e.g. 1m"
+    )]
+    min_duration: Option<Duration>,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum RangeKeyword {
+    Today,
+    Yesterday,
+    Week,
+    Month,
+}
+
+#[derive(Parser)]
+struct TimerFilterOptions {
+    #[clap(long, help = "Only include this timer, can be given several times")]
+    include: Vec<String>,
+
+    #[clap(long, help = "Leave out this timer, can be given several times")]
+    exclude: Vec<String>,
+}
+
+#[derive(Parser)]
+struct RecordsCommand {
+    #[clap(help = "Timer to list the records of (defaults to the active timer)")]
+    timer_name: Option<String>,
+
+    #[clap(long, help = "Show start times of the last day like \"2h ago\"")]
+    relative: bool,
+
+    #[clap(
+        long,
+        help = "Wrap comments at this many characters (defaults to the terminal width)"
+    )]
+    wrap_comments: Option<usize>,
+
+    #[clap(
+        long,
+        conflicts_with_all = &["yesterday", "week", "range", "since", "until"],
+        help = "Only list records starting today, same as --range today"
+    )]
+    today: bool,
+
+    #[clap(
+        long,
+        conflicts_with_all = &["week", "range", "since", "until"],
+        help = "Only list records starting yesterday, same as --range yesterday"
+    )]
+    yesterday: bool,
+
+    #[clap(
+        long,
+        conflicts_with_all = &["range", "since", "until"],
+        help = "Only list records starting this week, same as --range week"
+    )]
+    week: bool,
+
+    #[clap(long, help = "Only list the last this many of the matching records")]
+    limit: Option<usize>,
+
+    #[clap(long, help = "List the newest records first")]
+    reverse: bool,
+
+    #[clap(flatten)]
+    range: RangeOptions,
+}
+
+impl RecordsCommand {
+    /// The range given by `--today`, `--yesterday` or `--week`
+    fn range_shortcut(&self) -> Option<RangeKeyword> {
+        if self.today {
+            Some(RangeKeyword::Today)
+        } else if self.yesterday {
+            Some(RangeKeyword::Yesterday)
+        } else if self.week {
+            Some(RangeKeyword::Week)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Parser)]
+struct ReportCommand {
+    #[clap(help = "Timer to report on (defaults to the active timer)")]
+    timer_name: Option<String>,
+
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "day",
+        help = "How to group the records"
+    )]
+    group_by: GroupBy,
+
+    #[clap(
+        long,
+        parse(try_from_str = humantime::parse_duration),
+        help = "Round durations up to multiples of this, e.g. 15min"
+    )]
+    round: Option<Duration>,
+
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "record",
+        help = "Round every record, or the total of each group and the overall total"
+    )]
+    round_scope: RoundScope,
+
+    #[clap(
+        long,
+        parse(try_from_str = humantime::parse_duration),
+        help = "Bill at least this much for one record of each day, e.g. 30min"
+    )]
+    daily_minimum: Option<Duration>,
+
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "first",
+        help = "Which record of a day --daily-minimum applies to"
+    )]
+    minimum_on: MinimumOn,
+
+    #[clap(
+        long,
+        default_value = "local",
+        possible_values = &["local", "utc"],
+        parse(try_from_str = DayBoundary::parse),
+        help = "Which midnight starts a new day when grouping by day, week or month"
+    )]
+    day_boundary: DayBoundary,
+
+    #[clap(
+        long,
+        conflicts_with = "day-boundary",
+        allow_hyphen_values = true,
+        parse(try_from_str = parse_tz_offset),
+        help = "Start days at midnight at this fixed offset from UTC instead, e.g. +05:30"
+    )]
+    tz_offset: Option<FixedOffset>,
+
+    #[clap(
+        long,
+        help = "Only count the part of records straddling --since or --until that lies within the range"
+    )]
+    clip_range: bool,
+
+    #[clap(
+        long,
+        help = "Also count the running interval up to now, by default only stopped records count"
+    )]
+    include_running: bool,
+
+    #[clap(long, help = "Remember this report, for a later --since-last-report")]
+    mark: bool,
+
+    #[clap(
+        long,
+        help = "Only include records starting after the last report made with --mark"
+    )]
+    since_last_report: bool,
+
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "text",
+        help = "Print one line per group, an aligned table, or tab separated values for piping"
+    )]
+    format: ReportFormat,
+
+    #[clap(
+        long = "as",
+        arg_enum,
+        conflicts_with = "format",
+        help = "Only print the total as a bare number of this unit"
+    )]
+    unit: Option<DurationUnit>,
+
+    #[clap(
+        long,
+        conflicts_with_all = &["timer-name", "group-by", "format", "unit"],
+        help = "Print a CSV table of the seconds per day (rows) and timer (columns) of all timers"
+    )]
+    csv_by_day: bool,
+
+    #[clap(flatten)]
+    range: RangeOptions,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum MinimumOn {
+    First,
+    Last,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum RoundScope {
+    Record,
+    Total,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum DurationUnit {
+    Seconds,
+    Minutes,
+    Hours,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum ReportFormat {
+    Text,
+    Table,
+    Plain,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum GroupBy {
+    Day,
+    Week,
+    Month,
+    Tag,
+    Comment,
+}
+
+#[derive(Clone, Copy)]
+enum DayBoundary {
+    Local,
+    Utc,
+    /// A fixed offset from UTC, for systems without timezone data
+    Offset(FixedOffset),
+}
+
+impl GroupBy {
+    /// Heading of the group column in tables
+    fn column_name(self) -> &'static str {
+        match self {
+            GroupBy::Day => "Day",
+            GroupBy::Week => "Week",
+            GroupBy::Month => "Month",
+            GroupBy::Tag => "Tag",
+            GroupBy::Comment => "Comment",
+        }
+    }
+}
+
+impl DayBoundary {
+    fn parse(input: &str) -> Result<Self, AppError> {
+        match input {
+            "local" => Ok(DayBoundary::Local),
+            "utc" => Ok(DayBoundary::Utc),
+            _ => Err(AppError::InvalidDayBoundary(input.to_owned())),
+        }
+    }
+
+    /// The day `time` falls on
+    fn day_of(self, time: DateTime<Utc>) -> NaiveDate {
+        match self {
+            DayBoundary::Local => time.with_timezone(&Local).date().naive_local(),
+            DayBoundary::Utc => time.date().naive_utc(),
+            DayBoundary::Offset(offset) => time.with_timezone(&offset).date().naive_local(),
+        }
+    }
+}
+
+/// Parses an offset from UTC given as `+HH:MM`, `-HH:MM` or just `+HH`
+fn parse_tz_offset(input: &str) -> Result<FixedOffset, AppError> {
+    let invalid = || AppError::InvalidOffset(input.to_owned());
+    let trimmed = input.trim();
+    let (sign, unsigned) = if let Some(rest) = trimmed.strip_prefix('+') {
+        (1, rest)
+    } else if let Some(rest) = trimmed.strip_prefix('-') {
+        (-1, rest)
+    } else {
+        return Err(invalid());
+    };
+    let (hours, minutes) = unsigned.split_once(':').unwrap_or((unsigned, "0"));
+    let hours: i32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+    if !(0..60).contains(&minutes) {
+        return Err(invalid());
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(invalid)
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum ExportFormat {
+    Csv,
+    Json,
+    Jsonl,
+    /// Like jsonl, with the duration and local start and end times added
+    Ndjson,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[derive(Parser)]
+struct ExportCommand {
+    #[clap(help = "Timer to export (defaults to the active timer)")]
+    timer_name: Option<String>,
+
+    #[clap(long, conflicts_with = "timer-name", help = "Export all timers")]
+    all: bool,
+
+    #[clap(flatten)]
+    timers: TimerFilterOptions,
+
+    #[clap(long, arg_enum, default_value = "csv", help = "Format to export to")]
+    format: ExportFormat,
+
+    #[clap(
+        long,
+        help = "Write each timer to its own file in this directory instead of stdout"
+    )]
+    output_dir: Option<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with = "output-dir",
+        help = "Append the records newer than the last one in this file instead of writing to stdout"
+    )]
+    append: Option<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with = "append",
+        help = "Append per-timer subtotal rows and a total row to CSV exports"
+    )]
+    totals: bool,
+
+    #[clap(
+        long,
+        help = "Only export records whose comment matches this regular expression"
+    )]
+    comment_grep: Option<String>,
+
+    #[clap(flatten)]
+    range: RangeOptions,
+}
+
+#[derive(Parser)]
+struct HeatmapCommand {
+    #[clap(help = "Timer to show (defaults to the active timer)")]
+    timer_name: Option<String>,
+
+    #[clap(
+        long,
+        help = "Month to show as YYYY-MM (defaults to the current month)"
+    )]
+    month: Option<String>,
+}
+
+#[derive(Parser)]
+struct SearchCommand {
+    #[clap(help = "Text to search for in the record comments")]
+    query: String,
+
+    #[clap(
+        long,
+        conflicts_with_all = &["include", "exclude"],
+        help = "Only search the records of this timer"
+    )]
+    timer: Option<String>,
+
+    #[clap(long, help = "Treat the query as a regular expression")]
+    regex: bool,
+
+    #[clap(long, short, help = "Ignore case when matching")]
+    ignore_case: bool,
+
+    #[clap(long, short = 'v', help = "Find records whose comment does NOT match")]
+    invert: bool,
+
+    #[clap(flatten)]
+    timers: TimerFilterOptions,
+
+    #[clap(flatten)]
+    range: RangeOptions,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum CurrencyRounding {
+    /// To the nearest cent
+    #[clap(name = "2dp")]
+    TwoDecimals,
+    /// To the nearest whole amount
+    Nearest,
+    /// Up to the next cent
+    Ceil,
+}
+
+impl CurrencyRounding {
+    fn apply(self, amount: f64) -> f64 {
+        // get rid of binary representation errors first, so 10.005 is really
+        // half a cent above 10 and not slightly below
+        let cents = (amount * 100.0 * 1e6).round() / 1e6;
+
+        match self {
+            CurrencyRounding::TwoDecimals => cents.round() / 100.0,
+            CurrencyRounding::Nearest => (cents / 100.0).round(),
+            CurrencyRounding::Ceil => cents.ceil() / 100.0,
+        }
+    }
+}
+
+/// Decimal and thousands separators of amounts of money
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum NumberLocale {
+    /// 1,234.50
+    En,
+    /// 1.234,50
+    De,
+}
+
+impl NumberLocale {
+    fn separators(self) -> (char, char) {
+        match self {
+            NumberLocale::En => ('.', ','),
+            NumberLocale::De => (',', '.'),
+        }
+    }
+}
+
+/// Formats an amount of money with two decimals, with the separators of
+/// `locale` or plainly like `1234.50` without one
+fn format_amount(amount: f64, locale: Option<NumberLocale>) -> String {
+    let plain = format!("{:.2}", amount);
+    let locale = match locale {
+        Some(locale) => locale,
+        None => return plain,
+    };
+
+    let (decimal_separator, group_separator) = locale.separators();
+    let (sign, digits) = match plain.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", plain.as_str()),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+    let mut grouped = String::new();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i).is_multiple_of(3) {
+            grouped.push(group_separator);
+        }
+        grouped.push(digit);
+    }
+
+    format!("{}{}{}{}", sign, grouped, decimal_separator, fraction)
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum InvoiceFormat {
+    Text,
+    Markdown,
+    Csv,
+}
+
+#[derive(Parser)]
+struct InvoiceCommand {
+    #[clap(help = "Timer to invoice (defaults to the active timer)")]
+    timer_name: Option<String>,
+
+    #[clap(long, conflicts_with = "timer-name", help = "Invoice all timers")]
+    all: bool,
+
+    #[clap(flatten)]
+    timers: TimerFilterOptions,
+
+    #[clap(
+        long,
+        help = "Month to invoice as YYYY-MM (defaults to the current month)"
+    )]
+    month: Option<String>,
+
+    #[clap(long, help = "Currency to show amounts in [default: EUR]")]
+    currency: Option<String>,
+
+    #[clap(
+        long,
+        arg_enum,
+        help = "Separators of amounts in text and markdown invoices, e.g. de for 1.234,50"
+    )]
+    locale: Option<NumberLocale>,
+
+    #[clap(long, arg_enum, default_value = "text", help = "Format of the invoice")]
+    format: InvoiceFormat,
+
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "2dp",
+        help = "How to round the amount of each line"
+    )]
+    round_currency: CurrencyRounding,
+}
+
+#[derive(Parser)]
+struct CoalesceCommand {
+    #[clap(help = "Timer to merge the records of")]
+    timer_name: String,
+
+    #[clap(
+        long,
+        default_value = "1m",
+        parse(try_from_str = humantime::parse_duration),
+        help = "Merge records at most this far apart"
+    )]
+    max_gap: Duration,
+}
+
+#[derive(Parser)]
+struct WeekCommand {
+    #[clap(help = "Timer to show (defaults to the active timer)")]
+    timer_name: Option<String>,
+
+    #[clap(long, help = "Compare with the week before")]
+    compare: bool,
+
+    #[clap(
+        long,
+        help = "Also count the running interval up to now, by default only stopped records count"
+    )]
+    include_running: bool,
+
+    #[clap(
+        long = "as",
+        arg_enum,
+        conflicts_with = "compare",
+        help = "Only print the total as a bare number of this unit"
+    )]
+    unit: Option<DurationUnit>,
+}
+
+#[derive(Parser)]
+struct TotalCommand {
+    #[clap(long, arg_enum, help = "Also show the combined totals per group")]
+    group_by: Option<GroupBy>,
+
+    #[clap(
+        long = "as",
+        arg_enum,
+        conflicts_with = "group-by",
+        help = "Only print the total as a bare number of this unit"
+    )]
+    unit: Option<DurationUnit>,
+
+    #[clap(
+        long,
+        help = "Only count the part of records straddling --since or --until that lies within the range"
+    )]
+    clip_range: bool,
+
+    #[clap(flatten)]
+    timers: TimerFilterOptions,
+
+    #[clap(flatten)]
+    range: RangeOptions,
+}
+
+#[derive(Parser)]
+struct StatsCommand {
+    #[clap(help = "Timer to analyze (defaults to the active timer)")]
+    timer_name: Option<String>,
+
+    #[clap(
+        long,
+        parse(try_from_str = humantime::parse_duration),
+        help = "Leave out records shorter than this, e.g. 1m"
+    )]
+    min: Option<Duration>,
+
+    #[clap(
+        long,
+        parse(try_from_str = humantime::parse_duration),
+        help = "Leave out records longer than this, e.g. 12h"
+    )]
+    max: Option<Duration>,
+}
+
+#[derive(Parser)]
+struct FirstCommand {
+    #[clap(help = "Only look at the records of this timer")]
+    timer_name: Option<String>,
+}
+
+#[derive(Parser)]
+struct LastCommand {
+    #[clap(help = "Only look at the records of this timer")]
+    timer_name: Option<String>,
+}
+
+#[derive(Parser)]
+struct DurationCommand {
+    #[clap(help = "Start time")]
+    start: String,
+
+    #[clap(help = "End time")]
+    end: String,
+
+    #[clap(long, help = "Print as decimal hours, e.g. 1.50")]
+    decimal_hours: bool,
+
+    #[clap(
+        long,
+        default_value = "2",
+        requires = "decimal-hours",
+        help = "Number of decimals of --decimal-hours"
+    )]
+    decimal_precision: usize,
+
+    #[clap(
+        long,
+        requires = "decimal-hours",
+        help = "Round --decimal-hours to the nearest quarter hour, e.g. 1.75"
+    )]
+    quarter: bool,
+
+    #[clap(long, conflicts_with = "decimal-hours", help = "Print as H:MM:SS")]
+    hms: bool,
+}
+
+#[derive(Parser)]
+struct SchemaCommand {
+    #[clap(
+        arg_enum,
+        default_value = "state",
+        help = "Document to describe, exports describe a single record of json and jsonl exports"
+    )]
+    document: SchemaDocument,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum SchemaDocument {
+    State,
+    Export,
+    /// A line of ndjson exports
+    NdjsonExport,
+}
+
+#[derive(Parser)]
+struct EnvCommand {
+    #[clap(long, help = "Print as JSON")]
+    json: bool,
+
+    #[clap(long, help = "Print as indented JSON")]
+    json_pretty: bool,
+}
+
+/// Serializes `value` on one line, or indented over several if `pretty`
+fn json_string<T: Serialize>(value: &T, pretty: bool) -> Result<String, serde_json::Error> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum AppError {
+    TimerAlreadyRunning,
+    NoTimerRunning,
+    TimerNotRunning(String),
+    NoSuchTimer,
+    NoSuchRecord,
+    CommentRequired,
+    AmbiguousTimer,
+    BadTime(String),
+    InvalidDuration(String),
+    InvalidRate(String),
+    InvalidOffset(String),
+    InvalidDayBoundary(String),
+    InvalidMonth(String),
+    InvalidRegex(String),
+    CheckFailed(usize),
+    InvalidCsv(String),
+    MissingCsvColumn(&'static str),
+    CannotAppendJson,
+    TotalsNeedCsv,
+    NoSuchActiveTimer(String),
+    SplitOutsideRecord,
+    InvalidColor(String),
+    NotConfirmed,
+    EndBeforeStart,
+    AliasCollision(String),
+    NoSuchAlias(String),
+    NoHomeDirectory,
+    CannotCreateDataDir(PathBuf, ErrorKind),
+    StateLocked(PathBuf),
+    CannotLockState(PathBuf, ErrorKind),
+    CannotReadComment(PathBuf, ErrorKind),
+}
+
+impl Error for AppError {}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::TimerAlreadyRunning => f.write_str("Timer already running"),
+            AppError::NoTimerRunning => f.write_str("No timer running"),
+            AppError::TimerNotRunning(name) => write!(f, "No timer running for {}", name),
+            AppError::NoSuchTimer => f.write_str("No timer with this name"),
+            AppError::NoSuchRecord => f.write_str("No record with this index"),
+            AppError::CommentRequired => {
+                f.write_str("A comment is required, use --comment or --append-comment")
+            }
+            AppError::AmbiguousTimer => f.write_str("Multiple timers exist, please name one"),
+            AppError::BadTime(input) => write!(
+                f,
+                "Invalid time: \"{}\", expected a date and time like \"2022-04-01 09:30\" or \
+                 \"2022-04-01T09:30:00+02:00\", a time of today like \"09:30\", or a time \
+                 in the past like \"10m ago\"",
+                input
+            ),
+            AppError::InvalidDuration(input) => write!(f, "Invalid duration: {}", input),
+            AppError::InvalidRate(input) => {
+                write!(f, "Invalid rate: {} (expected a non-negative number)", input)
+            }
+            AppError::InvalidOffset(input) => {
+                write!(f, "Invalid offset: {} (expected +HH:MM or -HH:MM)", input)
+            }
+            AppError::InvalidDayBoundary(input) => write!(
+                f,
+                "Invalid day boundary: {} (expected local or utc, or a fixed offset via --tz-offset)",
+                input
+            ),
+            AppError::InvalidRegex(reason) => write!(f, "Invalid regular expression: {}", reason),
+            AppError::InvalidMonth(input) => {
+                write!(f, "Invalid month: {} (expected YYYY-MM)", input)
+            }
+            AppError::CheckFailed(count) => write!(f, "Found {} problem(s)", count),
+            AppError::InvalidCsv(reason) => write!(f, "Invalid CSV: {}", reason),
+            AppError::MissingCsvColumn(column) => {
+                write!(f, "CSV file has no \"{}\" column", column)
+            }
+            AppError::NoSuchActiveTimer(name) => {
+                write!(f, "Active timer {} does not exist", name)
+            }
+            AppError::EndBeforeStart => f.write_str("The end time is before the start time"),
+            AppError::AliasCollision(name) => {
+                write!(f, "{} is already the name of a timer", name)
+            }
+            AppError::NoSuchAlias(name) => write!(f, "No alias named {}", name),
+            AppError::NotConfirmed => f.write_str("Not confirmed, pass --yes to go ahead"),
+            AppError::InvalidColor(input) => {
+                write!(f, "Invalid color: {} (expected a name or #rrggbb)", input)
+            }
+            AppError::SplitOutsideRecord => {
+                f.write_str("The split time has to be between the start and end of the record")
+            }
+            AppError::CannotAppendJson => {
+                f.write_str("Only csv and jsonl exports can be appended to")
+            }
+            AppError::TotalsNeedCsv => f.write_str("--totals only works with --format csv"),
+            AppError::NoHomeDirectory => {
+                f.write_str("cannot determine data dir: no home directory found")
+            }
+            AppError::CannotCreateDataDir(path, kind) => {
+                write!(f, "cannot create data dir at {}: {}", path.display(), kind)
+            }
+            AppError::StateLocked(path) => write!(
+                f,
+                "the state is locked by another mtt process, remove {} if none is running",
+                path.display()
+            ),
+            AppError::CannotLockState(path, kind) => {
+                write!(f, "cannot create lock file {}: {}", path.display(), kind)
+            }
+            AppError::CannotReadComment(path, kind) => {
+                write!(f, "cannot read comment from {}: {}", path.display(), kind)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, JsonSchema, Serialize)]
+struct TimerRecord {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    comment: String,
+    #[serde(default)]
+    rate_override: Option<f64>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+impl TimerRecord {
+    fn new(start: DateTime<Utc>, end: DateTime<Utc>, comment: String) -> Self {
+        Self {
+            start,
+            end,
+            comment,
+            rate_override: None,
+            tags: vec![],
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        // in case start > end date, return 0s duration
+        (self.end - self.start).to_std().unwrap_or_default()
+    }
+
+    /// Cost of this record, billed at its own rate if it has one or `default_rate` otherwise
+    fn cost(&self, default_rate: Option<f64>) -> f64 {
+        let rate = self.rate_override.or(default_rate).unwrap_or_default();
+
+        rate * self.duration().as_secs_f64() / 3600.0
+    }
+}
+
+/// A completed or in-progress interval as shown in outputs
+#[derive(Debug, PartialEq, Serialize)]
+struct TimerRecordView<'a> {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    comment: &'a str,
+    duration_seconds: u64,
+}
+
+impl<'a> TimerRecordView<'a> {
+    fn new(start: DateTime<Utc>, end: DateTime<Utc>, comment: &'a str) -> Self {
+        Self {
+            start,
+            end,
+            comment,
+            duration_seconds: (end - start).num_seconds().max(0) as u64,
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration_seconds)
+    }
+}
+
+impl<'a> From<&'a TimerRecord> for TimerRecordView<'a> {
+    fn from(record: &'a TimerRecord) -> Self {
+        Self::new(record.start, record.end, &record.comment)
+    }
+}
+
+/// A correction of the total of a timer, logged instead of editing records
+#[derive(Clone, Deserialize, JsonSchema, Serialize)]
+struct Adjustment {
+    /// When the adjustment was made
+    time: DateTime<Utc>,
+    /// Signed number of seconds added to the total
+    seconds: i64,
+    comment: String,
+}
+
+#[derive(Default, Deserialize, JsonSchema, Serialize)]
+struct Timer {
+    records: Vec<TimerRecord>,
+    current_start: Option<DateTime<Utc>>,
+    #[serde(default)]
+    current_comment: Option<String>,
+    #[serde(default)]
+    rate: Option<f64>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    color: Option<String>,
+    #[serde(default)]
+    adjustments: Vec<Adjustment>,
+    #[serde(default)]
+    weekly_goal: Option<Duration>,
+}
+
+impl Timer {
+    fn start_timer(&mut self, start_time: DateTime<Utc>) -> Result<(), AppError> {
+        if self.current_start.is_some() {
+            return Err(AppError::TimerAlreadyRunning);
+        }
+
+        self.current_start = Some(start_time);
+
+        Ok(())
+    }
+
+    fn stop_timer(
+        &mut self,
+        stop_time: DateTime<Utc>,
+        comment: String,
+    ) -> Result<&mut TimerRecord, AppError> {
+        if let Some(current_start) = self.current_start {
+            self.records
+                .push(TimerRecord::new(current_start, stop_time, comment));
+            let record = self.records.last_mut().unwrap();
+
+            self.current_start = None;
+            self.current_comment = None;
+
+            Ok(record)
+        } else {
+            Err(AppError::NoTimerRunning)
+        }
+    }
+
+    /// Records the running interval up to `time` and starts a new one right
+    /// there, keeping the comment given on start
+    fn checkpoint(
+        &mut self,
+        time: DateTime<Utc>,
+        comment: String,
+    ) -> Result<&mut TimerRecord, AppError> {
+        let current_start = self.current_start.ok_or(AppError::NoTimerRunning)?;
+        self.records
+            .push(TimerRecord::new(current_start, time, comment));
+        self.current_start = Some(time);
+
+        Ok(self.records.last_mut().unwrap())
+    }
+
+    fn records_len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Whether nothing was ever tracked on this timer and it isn't running
+    fn is_empty(&self) -> bool {
+        self.records.is_empty() && self.adjustments.is_empty() && !self.is_running()
+    }
+
+    /// Duration of all records plus the adjustments, but never below zero
+    fn total_duration(&self) -> Duration {
+        let durations = self.records.iter().map(|record| record.duration());
+        let adjustment = self.adjustments.iter().map(|adjustment| adjustment.seconds);
+
+        adjust_duration(Duration::sum(durations), adjustment.sum())
+    }
+
+    /// Sum of the adjustments made within `[start, end)`, in seconds
+    fn adjustment_seconds(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> i64 {
+        self.adjustments
+            .iter()
+            .filter(|adjustment| adjustment.time >= start && adjustment.time < end)
+            .map(|adjustment| adjustment.seconds)
+            .sum()
+    }
+
+    /// Cost of all records, rounded once for the whole sum
+    fn cost(&self, rounding: CurrencyRounding) -> f64 {
+        let cost: f64 = self
+            .records
+            .iter()
+            .map(|record| record.cost(self.rate))
+            .sum();
+
+        rounding.apply(cost)
+    }
+
+    fn abort_timer(&mut self) -> Result<(), AppError> {
+        if self.current_start.take().is_some() {
+            self.current_comment = None;
+            Ok(())
+        } else {
+            Err(AppError::NoTimerRunning)
+        }
+    }
+
+    fn current_elapsed(&self, now: DateTime<Utc>) -> Option<Duration> {
+        self.current_start
+            .map(|start| (now - start).to_std().unwrap_or_default())
+    }
+
+    fn is_running(&self) -> bool {
+        self.current_start.is_some()
+    }
+
+    /// The latest start of a running interval or end of a record
+    fn last_activity(&self) -> Option<DateTime<Utc>> {
+        let last_end = self.records.iter().map(|record| record.end).max();
+
+        self.current_start.max(last_end)
+    }
+
+    /// The running interval as if it was stopped at `now`, without committing it
+    fn record_preview(&self, now: DateTime<Utc>) -> Option<TimerRecordView<'_>> {
+        let start = self.current_start?;
+        let comment = self.current_comment.as_deref().unwrap_or_default();
+
+        Some(TimerRecordView::new(start, now, comment))
+    }
+
+    /// The records followed by the running interval as if it was stopped at `now`
+    fn records_with_running(&self, now: DateTime<Utc>) -> std::borrow::Cow<'_, [TimerRecord]> {
+        match self.record_preview(now) {
+            Some(preview) => {
+                let mut records = self.records.clone();
+                records.push(TimerRecord::new(
+                    preview.start,
+                    preview.end,
+                    preview.comment.to_owned(),
+                ));
+                records.into()
+            }
+            None => self.records.as_slice().into(),
+        }
+    }
+
+    /// Sums the records starting within `[start, end)`, or with `clip` the
+    /// parts of all records that overlap it, plus the adjustments made within
+    fn duration_in_range(&self, start: DateTime<Utc>, end: DateTime<Utc>, clip: bool) -> Duration {
+        adjust_duration(
+            records_duration_in_range(&self.records, start, end, clip),
+            self.adjustment_seconds(start, end),
+        )
+    }
+
+    /// Returns the records matching `filter`, in the order they are stored
+    fn records_in_range<'a>(
+        &'a self,
+        filter: &'a RecordFilter,
+    ) -> impl Iterator<Item = &'a TimerRecord> + 'a {
+        self.records
+            .iter()
+            .filter(move |record| filter.matches(record))
+    }
+
+    /// Returns the adjustments matching `filter`, in the order they were made
+    fn adjustments_in_range<'a>(
+        &'a self,
+        filter: &'a RecordFilter,
+    ) -> impl Iterator<Item = &'a Adjustment> + 'a {
+        self.adjustments
+            .iter()
+            .filter(move |adjustment| filter.matches_adjustment(adjustment))
+    }
+
+    /// Like `duration_in_range`, for the range of `filter` and only the
+    /// records and adjustments otherwise matching it
+    fn total_in_range(&self, filter: &RecordFilter, clip: bool) -> Duration {
+        let (start, end) = filter.bounds();
+        let records = self
+            .records
+            .iter()
+            .filter(|record| filter.matches_apart_from_range(record));
+        let adjustment = self
+            .adjustments_in_range(filter)
+            .map(|adjustment| adjustment.seconds);
+
+        adjust_duration(
+            records_duration_in_range(records, start, end, clip),
+            adjustment.sum(),
+        )
+    }
+
+    /// Merges records that start at most `max_gap` after the previous one
+    /// ended into a single record spanning both, joining their comments and
+    /// tags. Records with different rate overrides are kept apart. Returns the
+    /// number of records that were merged into others.
+    fn merge_adjacent_records(&mut self, max_gap: Duration) -> usize {
+        let max_gap =
+            chrono::Duration::from_std(max_gap).unwrap_or_else(|_| chrono::Duration::max_value());
+        let mut records = std::mem::take(&mut self.records);
+        records.sort_by_key(|record| record.start);
+
+        let mut merged = 0;
+        for record in records {
+            match self.records.last_mut() {
+                Some(last)
+                    if record.start - last.end <= max_gap
+                        && record.rate_override == last.rate_override =>
+                {
+                    last.end = last.end.max(record.end);
+                    if !record.comment.is_empty() {
+                        if !last.comment.is_empty() {
+                            last.comment.push_str("; ");
+                        }
+                        last.comment.push_str(&record.comment);
+                    }
+                    for tag in record.tags {
+                        if !last.tags.contains(&tag) {
+                            last.tags.push(tag);
+                        }
+                    }
+                    merged += 1;
+                }
+                _ => self.records.push(record),
+            }
+        }
+
+        merged
+    }
+
+    /// Replaces the record at `index` with two records ending and starting at
+    /// `at`, which has to lie strictly within the record. Both parts keep the
+    /// comment, tags and rate of the original record.
+    fn split_record(&mut self, index: usize, at: DateTime<Utc>) -> Result<(), AppError> {
+        let record = self.records.get_mut(index).ok_or(AppError::NoSuchRecord)?;
+        if at <= record.start || at >= record.end {
+            return Err(AppError::SplitOutsideRecord);
+        }
+
+        let mut after = record.clone();
+        after.start = at;
+        record.end = at;
+        self.records.insert(index + 1, after);
+
+        Ok(())
+    }
+
+    /// Returns index pairs of records whose time spans overlap
+    fn find_overlaps(&self) -> Vec<(usize, usize)> {
+        let mut indices: Vec<usize> = (0..self.records_len()).collect();
+        indices.sort_by_key(|&i| self.records[i].start);
+
+        let mut overlaps = vec![];
+        let mut latest: Option<usize> = None;
+
+        for i in indices {
+            if let Some(latest_index) = latest {
+                let latest_end = self.records[latest_index].end;
+
+                if self.records[i].start < latest_end {
+                    overlaps.push((latest_index, i));
+                }
+                if self.records[i].end > latest_end {
+                    latest = Some(i);
+                }
+            } else {
+                latest = Some(i);
+            }
+        }
+
+        overlaps
+    }
+}
+
+/// Selects the records taken into account by reports and exports
+#[derive(Default)]
+struct RecordFilter {
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    min_duration: Duration,
+    /// Only records with a matching comment, as found by `search`
+    comment: Option<CommentMatcher>,
+}
+
+impl RecordFilter {
+    fn from_options(
+        options: &RangeOptions,
+        start_of_week: StartOfWeek,
+        now: DateTime<Utc>,
+    ) -> Result<Self, AppError> {
+        let parse = |time: &Option<String>| time.as_deref().map(|t| parse_time(t, now)).transpose();
+        let (since, until) = match options.range {
+            Some(keyword) => {
+                let (since, until) = resolve_range(keyword, start_of_week, now);
+                (Some(since), Some(until))
+            }
+            None => (parse(&options.since)?, parse(&options.until)?),
+        };
+
+        Ok(Self {
+            since,
+            until,
+            min_duration: options.min_duration.unwrap_or_default(),
+            comment: None,
+        })
+    }
+
+    fn matches(&self, record: &TimerRecord) -> bool {
+        self.since.is_none_or(|since| record.start >= since)
+            && self.until.is_none_or(|until| record.start < until)
+            && record.duration() >= self.min_duration
+            && self.matches_comment(record)
+    }
+
+    /// Whether `record` is long enough and has a matching comment
+    fn matches_apart_from_range(&self, record: &TimerRecord) -> bool {
+        record.duration() >= self.min_duration && self.matches_comment(record)
+    }
+
+    /// The range of the filter, unbounded ends left open as far as possible
+    fn bounds(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+        (
+            self.since.unwrap_or(chrono::MIN_DATETIME),
+            self.until.unwrap_or(chrono::MAX_DATETIME),
+        )
+    }
+
+    fn matches_comment(&self, record: &TimerRecord) -> bool {
+        self.comment
+            .as_ref()
+            .is_none_or(|matcher| matcher.is_match(&record.comment))
+    }
+
+    /// Adjustments count like records starting at the time they were made,
+    /// except that they have no length for `min_duration` to apply to
+    fn matches_adjustment(&self, adjustment: &Adjustment) -> bool {
+        self.since.is_none_or(|since| adjustment.time >= since)
+            && self.until.is_none_or(|until| adjustment.time < until)
+            && self
+                .comment
+                .as_ref()
+                .is_none_or(|matcher| matcher.is_match(&adjustment.comment))
+    }
+
+    /// Returns the in-range portions of all records overlapping the range,
+    /// instead of selecting whole records by their start
+    fn clipped_records(&self, records: &[TimerRecord]) -> Vec<TimerRecord> {
+        records
+            .iter()
+            .filter(|record| self.matches_apart_from_range(record))
+            .filter_map(|record| {
+                let start = self.since.unwrap_or(record.start).max(record.start);
+                let end = self.until.unwrap_or(record.end);
+                let duration = clip_record(record, start, end)?;
+
+                let mut clipped = record.clone();
+                clipped.start = start;
+                clipped.end = start + chrono::Duration::from_std(duration).ok()?;
+                Some(clipped)
+            })
+            .collect()
+    }
+}
+
+/// Adds a signed number of seconds to `duration`, stopping at zero
+fn adjust_duration(duration: Duration, seconds: i64) -> Duration {
+    let difference = Duration::from_secs(seconds.unsigned_abs());
+
+    if seconds < 0 {
+        duration.saturating_sub(difference)
+    } else {
+        duration + difference
+    }
+}
+
+/// Like `Timer::duration_in_range` without the adjustments, for records that
+/// aren't stored in a timer like the ones of `Timer::records_with_running`
+fn records_duration_in_range<'a>(
+    records: impl IntoIterator<Item = &'a TimerRecord>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    clip: bool,
+) -> Duration {
+    records
+        .into_iter()
+        .map(|record| {
+            if clip {
+                clip_record(record, start, end).unwrap_or_default()
+            } else if record.start >= start && record.start < end {
+                record.duration()
+            } else {
+                Duration::ZERO
+            }
+        })
+        .sum()
+}
+
+/// Returns the duration of the part of `record` within `[start, end)`, or
+/// `None` if they don't overlap
+fn clip_record(record: &TimerRecord, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<Duration> {
+    let clipped_start = record.start.max(start);
+    let clipped_end = record.end.min(end);
+
+    (clipped_end - clipped_start)
+        .to_std()
+        .ok()
+        .filter(|duration| !duration.is_zero())
+}
+
+/// The state file, `state.json` in the data dir
+#[derive(Default, Deserialize, JsonSchema, Serialize)]
+struct AppState {
+    timers: HashMap<String, Timer>,
+    active_timer: Option<String>,
+    #[serde(default)]
+    last_report_at: Option<DateTime<Utc>>,
+    /// Short names mapping to the timers they stand for
+    #[serde(default)]
+    aliases: HashMap<String, String>,
+}
+
+impl AppState {
+    fn get_active_timer(&self) -> Option<&Timer> {
+        match &self.active_timer {
+            Some(timer_name) => self.timers.get(timer_name),
+            None => None,
+        }
+    }
+
+    fn has_active_timer(&self) -> bool {
+        self.active_timer_name().is_some()
+    }
+
+    /// Name of the active timer, `None` if there is none or it doesn't exist
+    fn active_timer_name(&self) -> Option<&str> {
+        self.active_timer
+            .as_deref()
+            .filter(|name| self.timers.contains_key(*name))
+    }
+
+    fn active_record_preview(&self, now: DateTime<Utc>) -> Option<TimerRecordView<'_>> {
+        self.get_active_timer()?.record_preview(now)
+    }
+
+    /// Whether the active timer exists and is running
+    fn is_running(&self) -> bool {
+        self.has_active_timer() && self.get_active_timer().is_some_and(Timer::is_running)
+    }
+
+    fn set_timer_active(&mut self, timer_name: &str) -> Result<(), AppError> {
+        let timer_name = self.canonical_name(timer_name);
+        if self.timers.contains_key(timer_name) {
+            self.active_timer = Some(String::from(timer_name));
+
+            Ok(())
+        } else {
+            Err(AppError::NoSuchTimer)
+        }
+    }
+
+    fn create_timer(&mut self, name: &str) -> Option<&Timer> {
+        let name = self.canonical_name(name).to_owned();
+        let name = name.as_str();
+        if self.timers.contains_key(name) {
+            None
+        } else {
+            let timer = Timer::default();
+            self.timers.insert(name.to_string(), timer);
+
+            self.get_timer(name)
+        }
+    }
+
+    fn get_timer(&self, name: &str) -> Option<&Timer> {
+        self.timers.get(self.canonical_name(name))
+    }
+
+    /// Returns the timer `name` stands for if it is an alias, or `name` itself
+    fn canonical_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.aliases.get(name).map_or(name, String::as_str)
+    }
+
+    /// Makes `alias` stand for the timer `timer_name`, which may be an alias itself
+    fn set_alias(&mut self, alias: &str, timer_name: &str) -> Result<(), AppError> {
+        if self.timers.contains_key(alias) {
+            return Err(AppError::AliasCollision(alias.to_owned()));
+        }
+
+        let timer_name = self.canonical_name(timer_name).to_owned();
+        if !self.timers.contains_key(&timer_name) {
+            return Err(AppError::NoSuchTimer);
+        }
+        self.aliases.insert(alias.to_owned(), timer_name);
+
+        Ok(())
+    }
+
+    /// Looks up the named timer, or the active timer if no name is given
+    fn resolve_timer<'a>(
+        &'a self,
+        name: Option<&'a str>,
+    ) -> Result<(&'a str, &'a Timer), AppError> {
+        let name = name
+            .or(self.active_timer_name())
+            .ok_or(AppError::NoSuchTimer)?;
+        let name = self.canonical_name(name);
+        let timer = self.get_timer(name).ok_or(AppError::NoSuchTimer)?;
+
+        Ok((name, timer))
+    }
+
+    fn get_timer_mut(&mut self, name: &str) -> Option<&mut Timer> {
+        let name = self.aliases.get(name).map_or(name, String::as_str);
+        self.timers.get_mut(name)
+    }
+
+    fn get_active_timer_mut(&mut self) -> Option<&mut Timer> {
+        match &self.active_timer {
+            Some(timer_name) => self.timers.get_mut(timer_name),
+            None => None,
+        }
+    }
+
+    /// Checkpoints every timer running for longer than `interval` at `now`,
+    /// so at most that much time is lost if it is never stopped. Returns the
+    /// number of checkpointed timers.
+    fn maybe_checkpoint(&mut self, now: DateTime<Utc>, interval: Duration) -> usize {
+        let mut checkpointed = 0;
+
+        for timer in self.timers.values_mut() {
+            if timer
+                .current_elapsed(now)
+                .is_some_and(|elapsed| elapsed > interval)
+            {
+                let comment = timer.current_comment.clone().unwrap_or_default();
+                if timer.checkpoint(now, comment).is_ok() {
+                    checkpointed += 1;
+                }
+            }
+        }
+
+        checkpointed
+    }
+
+    /// Removes the records of all timers and stops running ones without
+    /// recording them. Returns the number of removed records.
+    fn reset_all(&mut self) -> usize {
+        let removed = self.record_count();
+
+        for timer in self.timers.values_mut() {
+            timer.records.clear();
+            timer.adjustments.clear();
+            timer.current_start = None;
+            timer.current_comment = None;
+        }
+
+        removed
+    }
+
+    /// Renames the tag `old` to `new` in the records of all timers, keeping
+    /// only one of them where a record carried both. Returns the number of
+    /// changed records.
+    fn rename_tag(&mut self, old: &str, new: &str) -> usize {
+        let mut renamed = 0;
+
+        for record in self
+            .timers
+            .values_mut()
+            .flat_map(|timer| &mut timer.records)
+        {
+            if !record.tags.iter().any(|tag| tag == old) {
+                continue;
+            }
+
+            let mut tags: Vec<String> = Vec::with_capacity(record.tags.len());
+            for tag in record.tags.drain(..) {
+                let tag = if tag == old { new.to_owned() } else { tag };
+                if tag != new || !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+            record.tags = tags;
+            renamed += 1;
+        }
+
+        renamed
+    }
+
+    /// Removes `tag` from the records of the named timer, or of all timers.
+    /// Returns the number of changed records.
+    fn remove_tag(&mut self, tag: &str, timer_name: Option<&str>) -> Result<usize, AppError> {
+        let timers: Vec<&mut Timer> = match timer_name {
+            Some(name) => vec![self.get_timer_mut(name).ok_or(AppError::NoSuchTimer)?],
+            None => self.timers.values_mut().collect(),
+        };
+        let mut removed = 0;
+
+        for record in timers.into_iter().flat_map(|timer| &mut timer.records) {
+            let before = record.tags.len();
+            record.tags.retain(|other| other != tag);
+            if record.tags.len() != before {
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// The record that ended last of all timers, along with its timer's name
+    fn last_record(&self) -> Option<(&str, &TimerRecord)> {
+        self.timers
+            .iter()
+            .flat_map(|(name, timer)| {
+                timer
+                    .records
+                    .iter()
+                    .map(move |record| (name.as_str(), record))
+            })
+            .max_by_key(|&(name, record)| (record.end, std::cmp::Reverse(name)))
+    }
+
+    /// The record that started first of all timers, along with its timer's name
+    fn first_record(&self) -> Option<(&str, &TimerRecord)> {
+        self.timers
+            .iter()
+            .flat_map(|(name, timer)| {
+                timer
+                    .records
+                    .iter()
+                    .map(move |record| (name.as_str(), record))
+            })
+            .min_by_key(|&(name, record)| (record.start, name))
+    }
+
+    /// Number of stopped records of all timers
+    fn record_count(&self) -> usize {
+        self.timers.values().map(Timer::records_len).sum()
+    }
+
+    /// The names of all timers in alphabetical order
+    fn timer_names_sorted(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self.timers.keys().collect();
+        names.sort_unstable();
+
+        names
+    }
+
+    /// Returns the adjustments of the named timers matching `filter`, timer by timer
+    fn adjustments_in_range<'a>(
+        &'a self,
+        timer_names: &'a [&'a str],
+        filter: &'a RecordFilter,
+    ) -> impl Iterator<Item = &'a Adjustment> + 'a {
+        timer_names
+            .iter()
+            .filter_map(move |&name| self.get_timer(name))
+            .flat_map(move |timer| timer.adjustments_in_range(filter))
+    }
+
+    /// Sums up the records and adjustments of the named timers matching
+    /// `filter`, with `clip` only the parts of the records within its range
+    fn grand_total(&self, timer_names: &[&str], filter: &RecordFilter, clip: bool) -> Duration {
+        timer_names
+            .iter()
+            .filter_map(|&name| self.get_timer(name))
+            .map(|timer| timer.total_in_range(filter, clip))
+            .sum()
+    }
+
+    /// Reads the state, falling back to the backup next to it if the state
+    /// file is corrupt. Only fails if neither of them can be parsed, or if the
+    /// state is inconsistent and `strict` is set.
+    fn read_from_file(path: &Path, strict: bool) -> Result<Self, Box<dyn Error>> {
+        let mut state = Self::parse_file(path)?;
+        state.validate_active_timer(strict)?;
+
+        Ok(state)
+    }
+
+    /// Reads a backup written by `backup`, which unlike the state file has to exist
+    fn read_backup(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut state: Self = serde_json::from_reader(File::open(path)?)?;
+        state.validate_active_timer(false)?;
+
+        Ok(state)
+    }
+
+    fn parse_file(path: &Path) -> Result<Self, serde_json::Error> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return Ok(AppState::default()),
+        };
+
+        serde_json::from_reader(file).or_else(|err| {
+            let backup_path = backup_path_of(path);
+            let backup = File::open(&backup_path)
+                .ok()
+                .and_then(|file| serde_json::from_reader(file).ok())
+                .ok_or(err)?;
+
+            eprintln!(
+                "mtt: {} is corrupt, recovered the state from {}",
+                path.display(),
+                backup_path.display()
+            );
+            Ok(backup)
+        })
+    }
+
+    /// Makes sure `active_timer` names an existing timer, e.g. after editing
+    /// the state file by hand. A dangling name is an error in `strict` mode
+    /// and cleared with a warning otherwise.
+    fn validate_active_timer(&mut self, strict: bool) -> Result<(), AppError> {
+        match &self.active_timer {
+            Some(name) if !self.timers.contains_key(name) => {
+                if strict {
+                    return Err(AppError::NoSuchActiveTimer(name.clone()));
+                }
+
+                eprintln!("mtt: active timer {} does not exist, clearing it", name);
+                self.active_timer = None;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the state to a temporary file first and moves it into place, so
+    /// a failed write never leaves a truncated state behind. The previous
+    /// state is kept as a backup as long as it is readable. With `pretty` the
+    /// JSON is indented, for readable diffs of a state kept in version control.
+    fn write_to_file(&self, path: &Path, pretty: bool) -> Result<(), serde_json::Error> {
+        let temp_path = path.with_extension("json.tmp");
+        let file = File::create(&temp_path).map_err(serde_json::Error::io)?;
+        if pretty {
+            serde_json::to_writer_pretty(&file, self)?;
+        } else {
+            serde_json::to_writer(&file, self)?;
+        }
+        file.sync_all().map_err(serde_json::Error::io)?;
+
+        let previous_is_valid = std::fs::read(path)
+            .map(|contents| serde_json::from_slice::<AppState>(&contents).is_ok())
+            .unwrap_or(false);
+        if previous_is_valid {
+            std::fs::rename(path, backup_path_of(path)).map_err(serde_json::Error::io)?;
+        }
+
+        std::fs::rename(&temp_path, path).map_err(serde_json::Error::io)
+    }
+}
+
+/// The backup of a state file, `state.json.bak` for `state.json`
+fn backup_path_of(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    file_name.push(".bak");
+
+    path.with_file_name(file_name)
+}
+
+/// How often to try taking the state lock before giving up, `LOCK_RETRY_DELAY` apart
+const LOCK_ATTEMPTS: u32 = 50;
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Exclusive access to a state file, held through `state.json.lock` next to
+/// it and released when dropped. The lock file holds the pid of its owner, so
+/// a lock left behind by a crashed process can be taken over.
+struct StateLock {
+    path: PathBuf,
+}
+
+impl StateLock {
+    fn acquire(state_path: &Path, attempts: u32) -> Result<Self, AppError> {
+        let mut file_name = state_path.file_name().unwrap_or_default().to_owned();
+        file_name.push(".lock");
+        let path = state_path.with_file_name(file_name);
+        if Self::is_stale(&path) {
+            let _ = std::fs::remove_file(&path);
+        }
+
+        for attempt in 1..=attempts.max(1) {
+            let created = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path);
+            match created {
+                Ok(mut file) => {
+                    let _ = write!(file, "{}", std::process::id());
+                    return Ok(Self { path });
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    if attempt < attempts {
+                        std::thread::sleep(LOCK_RETRY_DELAY);
+                    }
+                }
+                Err(err) => return Err(AppError::CannotLockState(path, err.kind())),
+            }
+        }
+
+        Err(AppError::StateLocked(path))
+    }
+
+    /// Whether the lock at `path` belongs to a process that no longer runs.
+    /// Only detectable where `/proc` lists the running processes.
+    fn is_stale(path: &Path) -> bool {
+        let processes = Path::new("/proc");
+        if !processes.join("self").exists() {
+            return false;
+        }
+
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|pid| pid.trim().parse::<u32>().ok())
+            .is_some_and(|pid| !processes.join(pid.to_string()).exists())
+    }
+}
+
+impl Drop for StateLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Loads the state at `path`, lets `apply` work on it and writes it back if
+/// `write` is set. Writing commands hold the lock from loading to writing,
+/// so changes other mtt processes made in the meantime aren't lost.
+fn with_state<T>(
+    path: &Path,
+    strict: bool,
+    write: bool,
+    pretty: bool,
+    lock_attempts: u32,
+    apply: impl FnOnce(&mut AppState) -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    let _lock = if write {
+        Some(StateLock::acquire(path, lock_attempts)?)
+    } else {
+        None
+    };
+
+    let mut state = AppState::read_from_file(path, strict)?;
+    let result = apply(&mut state)?;
+    if write {
+        state.write_to_file(path, pretty)?;
+    }
+
+    Ok(result)
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+struct Config {
+    /// Refuse to create timers on `start`, so a mistyped name is always an error
+    no_create: bool,
+    /// Format of the `prompt` indicator
+    prompt_format: Option<String>,
+    /// Refuse to `stop` without a comment
+    comment_required: bool,
+    /// Currency of invoices
+    currency: Option<String>,
+    /// Comment of records stopped without any comment
+    comment_default: Option<String>,
+    /// Like `comment_default`, but with placeholders as in `stop --comment-template`
+    comment_template: Option<String>,
+    /// Number of units `show` prints durations with, the largest ones first
+    max_units: Option<usize>,
+    /// Refuse to load an inconsistent state instead of repairing it
+    strict_state: bool,
+    /// First day of the week in week reports and the heatmap
+    start_of_week: StartOfWeek,
+    /// Always show the weekly goal progress in `show`, as with `--goal`
+    show_goal: bool,
+    /// Write the state file indented, as with `--pretty-state`
+    pretty_state: bool,
+    /// Separators of costs and invoice amounts, as with `--locale`
+    locale: Option<NumberLocale>,
+    /// Warn about overlapping records on every run, as with `--warn-overlap`
+    warn_on_overlap: bool,
+    /// Checkpoint timers running for longer than this whenever a command
+    /// saves the state, e.g. "1h". Read-only commands like `show` or
+    /// `running` never checkpoint, so they don't have to lock and rewrite
+    /// the state.
+    auto_checkpoint: Option<String>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum StartOfWeek {
+    #[default]
+    Monday,
+    Sunday,
+}
+
+impl StartOfWeek {
+    /// Position of `day` within its week, from 0 to 6
+    fn days_into_week(self, day: NaiveDate) -> u32 {
+        match self {
+            StartOfWeek::Monday => day.weekday().num_days_from_monday(),
+            StartOfWeek::Sunday => day.weekday().num_days_from_sunday(),
+        }
+    }
+
+    /// The first day of the week containing `day`
+    fn week_start(self, day: NaiveDate) -> NaiveDate {
+        day - chrono::Duration::days(self.days_into_week(day).into())
+    }
+
+    fn weekday_header(self) -> &'static str {
+        match self {
+            StartOfWeek::Monday => "Mo Tu We Th Fr Sa Su",
+            StartOfWeek::Sunday => "Su Mo Tu We Th Fr Sa",
+        }
+    }
+}
+
+impl Config {
+    fn read_from_file(path: &Path) -> Result<Self, serde_json::Error> {
+        let file = File::open(path);
+
+        if let Ok(file) = file {
+            serde_json::from_reader(file)
+        } else {
+            Ok(Config::default())
+        }
+    }
+}
+
+/// Everything `env` reports about the environment mtt runs in
+#[derive(Serialize)]
+struct EnvInfo<'a> {
+    version: &'static str,
+    data_dir: &'a Path,
+    state_path: &'a Path,
+    config_path: &'a Path,
+    config_found: bool,
+    total_records: usize,
+    timezone: String,
+    color: bool,
+    config: &'a Config,
+}
+
+impl<'a> EnvInfo<'a> {
+    fn new(
+        state_path: &'a Path,
+        config_path: &'a Path,
+        config: &'a Config,
+        total_records: usize,
+        color: bool,
+        now: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            data_dir: state_path.parent().unwrap_or(state_path),
+            state_path,
+            config_path,
+            config_found: config_path.is_file(),
+            total_records,
+            timezone: now.with_timezone(&Local).offset().to_string(),
+            color,
+            config,
+        }
+    }
+}
+
+fn get_project_dirs() -> Result<ProjectDirs, AppError> {
+    ProjectDirs::from("eu", "markuskasten", "mtt").ok_or(AppError::NoHomeDirectory)
+}
+
+fn get_statefile_path() -> Result<PathBuf, AppError> {
+    statefile_path_in(get_project_dirs()?.data_dir())
+}
+
+fn get_configfile_path() -> Result<PathBuf, AppError> {
+    Ok(get_project_dirs()?.config_dir().join("config.json"))
+}
+
+fn statefile_path_in(data_dir: &Path) -> Result<PathBuf, AppError> {
+    let state_filename = "state.json";
+
+    create_dir_all(data_dir)
+        .map_err(|err| AppError::CannotCreateDataDir(data_dir.to_path_buf(), err.kind()))?;
+
+    Ok(data_dir.join(state_filename))
+}
+
+/// Parses a user supplied point in time. Accepts RFC 3339 timestamps, local
+/// date and time (`YYYY-MM-DD HH:MM[:SS]`), a local wall clock time (`HH:MM`)
+/// of today and relative times like `10m ago`.
+fn parse_time(input: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, AppError> {
+    let input = input.trim();
+    let invalid = || AppError::BadTime(input.to_owned());
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let naive = NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M"));
+    if let Ok(naive) = naive {
+        let local = Local
+            .from_local_datetime(&naive)
+            .single()
+            .ok_or_else(invalid)?;
+        return Ok(local.with_timezone(&Utc));
+    }
+
+    if let Ok(time) = NaiveTime::parse_from_str(input, "%H:%M") {
+        let local = now
+            .with_timezone(&Local)
+            .date()
+            .and_time(time)
+            .ok_or_else(invalid)?;
+        return Ok(local.with_timezone(&Utc));
+    }
+
+    if let Some(ago) = input.strip_suffix("ago") {
+        let duration = humantime::parse_duration(ago.trim()).map_err(|_| invalid())?;
+        let duration = chrono::Duration::from_std(duration).map_err(|_| invalid())?;
+        return Ok(now - duration);
+    }
+
+    Err(invalid())
+}
+
+/// Returns the bounds of the local day, week (starting on `start_of_week`) or
+/// month around `now` as a half-open range
+fn resolve_range(
+    keyword: RangeKeyword,
+    start_of_week: StartOfWeek,
+    now: DateTime<Utc>,
+) -> (DateTime<Utc>, DateTime<Utc>) {
+    let today = DayBoundary::Local.day_of(now);
+    let (first, end) = match keyword {
+        RangeKeyword::Today => (today, today.succ()),
+        RangeKeyword::Yesterday => (today.pred(), today),
+        RangeKeyword::Week => {
+            let first = start_of_week.week_start(today);
+            (first, first + chrono::Duration::days(7))
+        }
+        RangeKeyword::Month => {
+            let first = today.with_day(1).unwrap();
+            let end = match first.month() {
+                12 => NaiveDate::from_ymd(first.year() + 1, 1, 1),
+                month => NaiveDate::from_ymd(first.year(), month + 1, 1),
+            };
+            (first, end)
+        }
+    };
+
+    (local_midnight(first), local_midnight(end))
+}
+
+/// The start of `day` in the local timezone
+fn local_midnight(day: NaiveDate) -> DateTime<Utc> {
+    let midnight = day.and_hms(0, 0, 0);
+    // midnight may be skipped by a DST change, fall back to reading it as UTC
+    Local.from_local_datetime(&midnight).earliest().map_or_else(
+        || Utc.from_utc_datetime(&midnight),
+        |time| time.with_timezone(&Utc),
+    )
+}
+
+/// Parses a duration like `1h 30m` with an optional leading `-` or `+` into seconds
+fn parse_signed_duration(input: &str) -> Result<i64, AppError> {
+    let invalid = || AppError::InvalidDuration(input.to_owned());
+    let input = input.trim();
+    let (sign, unsigned) = match input.strip_prefix('-') {
+        Some(unsigned) => (-1, unsigned),
+        None => (1, input.strip_prefix('+').unwrap_or(input)),
+    };
+
+    let duration = humantime::parse_duration(unsigned.trim()).map_err(|_| invalid())?;
+    let duration = chrono::Duration::from_std(duration).map_err(|_| invalid())?;
+
+    Ok(sign * duration.num_seconds())
+}
+
+/// Parses an hourly rate, refusing negative and non-finite values
+fn parse_rate(input: &str) -> Result<f64, AppError> {
+    match input.trim().parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate >= 0.0 => Ok(rate),
+        _ => Err(AppError::InvalidRate(input.to_owned())),
+    }
+}
+
+/// Splits CSV text into rows of fields, handling quoted fields with
+/// embedded separators, quotes and line breaks.
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, AppError> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+        } else {
+            match c {
+                '"' => in_quotes = true,
+                ',' => row.push(std::mem::take(&mut field)),
+                '\r' => {}
+                '\n' => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                _ => field.push(c),
+            }
+        }
+    }
+
+    if in_quotes {
+        return Err(AppError::InvalidCsv("unterminated quoted field".to_owned()));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    // skip blank lines
+    rows.retain(|row| !(row.len() == 1 && row[0].trim().is_empty()));
+
+    Ok(rows)
+}
+
+/// Positions of the known columns in an imported CSV file
+struct CsvColumns {
+    start: usize,
+    end: usize,
+    comment: Option<usize>,
+    tags: Option<usize>,
+    timer: Option<usize>,
+}
+
+impl CsvColumns {
+    fn from_header(header: &[String]) -> Result<Self, AppError> {
+        let find = |name: &str| {
+            header
+                .iter()
+                .position(|column| column.trim().eq_ignore_ascii_case(name))
+        };
+
+        Ok(Self {
+            start: find("start").ok_or(AppError::MissingCsvColumn("start"))?,
+            end: find("end").ok_or(AppError::MissingCsvColumn("end"))?,
+            comment: find("comment"),
+            tags: find("tags"),
+            timer: find("timer"),
+        })
+    }
+}
+
+/// Imports records from CSV text, creating timers as needed. Records of files
+/// without a timer column go to `default_timer`. Returns the number of imported records.
+fn import_csv(
+    state: &mut AppState,
+    text: &str,
+    default_timer: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<usize, AppError> {
+    let mut rows = parse_csv(text)?.into_iter();
+    let header = rows
+        .next()
+        .ok_or_else(|| AppError::InvalidCsv("missing header row".to_owned()))?;
+    let columns = CsvColumns::from_header(&header)?;
+
+    let mut imported = vec![];
+    for (line, row) in rows.enumerate() {
+        let field = |index: usize| row.get(index).map(|field| field.trim()).unwrap_or("");
+        let optional_field = |index: Option<usize>| index.map(field).unwrap_or("");
+
+        let timer_name = match optional_field(columns.timer) {
+            "" => default_timer.ok_or(AppError::NoSuchTimer)?,
+            name => name,
+        };
+
+        let mut record = TimerRecord::new(
+            parse_time(field(columns.start), now)?,
+            parse_time(field(columns.end), now)?,
+            optional_field(columns.comment).to_owned(),
+        );
+        record.tags = optional_field(columns.tags)
+            .split(';')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_owned)
+            .collect();
+
+        if record.end < record.start {
+            return Err(AppError::InvalidCsv(format!(
+                "record on line {} ends before it starts",
+                line + 2
+            )));
+        }
+
+        imported.push((timer_name.to_owned(), record));
+    }
+
+    let count = imported.len();
+    for (timer_name, record) in imported {
+        state.create_timer(&timer_name);
+        state
+            .get_timer_mut(&timer_name)
+            .unwrap()
+            .records
+            .push(record);
+    }
+
+    Ok(count)
+}
+
+/// Quotes a CSV field if it contains separators, quotes or line breaks
+fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+/// A record along with the name of its timer, as written by `export`
+#[derive(JsonSchema, Serialize)]
+struct ExportRecord<'a> {
+    timer: &'a str,
+    #[serde(flatten)]
+    record: &'a TimerRecord,
+}
+
+fn write_csv<'a, W: Write>(
+    out: &mut W,
+    records: impl IntoIterator<Item = ExportRecord<'a>>,
+) -> std::io::Result<()> {
+    writeln!(out, "timer,start,end,duration,comment,tags")?;
+
+    write_csv_rows(out, records)
+}
+
+fn write_csv_rows<'a, W: Write>(
+    out: &mut W,
+    records: impl IntoIterator<Item = ExportRecord<'a>>,
+) -> std::io::Result<()> {
+    for ExportRecord { timer, record } in records {
+        writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            csv_field(timer),
+            record.start.to_rfc3339(),
+            record.end.to_rfc3339(),
+            record.duration().as_secs(),
+            csv_field(&record.comment),
+            csv_field(&record.tags.join(";")),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes the records like `write_csv`, followed by a subtotal row per timer
+/// (if there is more than one) and a final total row. The label goes into the
+/// timer column and the start and end columns are left blank.
+fn write_csv_with_totals<'a, W: Write>(
+    out: &mut W,
+    records: impl IntoIterator<Item = ExportRecord<'a>>,
+) -> std::io::Result<()> {
+    writeln!(out, "timer,start,end,duration,comment,tags")?;
+
+    let mut subtotals: Vec<(&str, Duration)> = vec![];
+    for export in records {
+        match subtotals.last_mut() {
+            Some((timer, subtotal)) if *timer == export.timer => {
+                *subtotal += export.record.duration()
+            }
+            _ => subtotals.push((export.timer, export.record.duration())),
+        }
+        write_csv_rows(out, std::iter::once(export))?;
+    }
+
+    if subtotals.len() > 1 {
+        for (timer, subtotal) in &subtotals {
+            writeln!(
+                out,
+                "{},,,{},,",
+                csv_field(&format!("Subtotal {}", timer)),
+                subtotal.as_secs()
+            )?;
+        }
+    }
+    let total: Duration = subtotals.iter().map(|(_, subtotal)| *subtotal).sum();
+    writeln!(out, "Total,,,{},,", total.as_secs())
+}
+
+fn write_json<'a, W: Write>(
+    out: &mut W,
+    records: impl IntoIterator<Item = ExportRecord<'a>>,
+) -> Result<(), serde_json::Error> {
+    use serde::Serializer;
+
+    serde_json::Serializer::new(&mut *out).collect_seq(records)?;
+    writeln!(out).map_err(serde_json::Error::io)
+}
+
+/// Version of the lines of ndjson exports, increased whenever fields change
+/// in a way consumers might notice
+const NDJSON_SCHEMA_VERSION: u32 = 1;
+
+/// A line of ndjson exports, a record along with values derived from it so
+/// consumers don't have to compute them
+#[derive(JsonSchema, Serialize)]
+struct NdjsonRecord<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    export: ExportRecord<'a>,
+    duration_seconds: u64,
+    /// The start in the local time zone of the export, as RFC 3339
+    start_local: String,
+    /// The end in the local time zone of the export, as RFC 3339
+    end_local: String,
+}
+
+impl<'a> NdjsonRecord<'a> {
+    fn new(export: ExportRecord<'a>) -> Self {
+        let record = export.record;
+
+        Self {
+            schema_version: NDJSON_SCHEMA_VERSION,
+            duration_seconds: record.duration().as_secs(),
+            start_local: record.start.with_timezone(&Local).to_rfc3339(),
+            end_local: record.end.with_timezone(&Local).to_rfc3339(),
+            export,
+        }
+    }
+}
+
+/// Writes a versioned JSON object per line, one record at a time
+fn write_ndjson<'a, W: Write>(
+    out: &mut W,
+    records: impl IntoIterator<Item = ExportRecord<'a>>,
+) -> Result<(), serde_json::Error> {
+    for record in records {
+        serde_json::to_writer(&mut *out, &NdjsonRecord::new(record))?;
+        writeln!(out).map_err(serde_json::Error::io)?;
+    }
+
+    Ok(())
+}
+
+/// Writes one JSON object per line, one record at a time
+fn write_jsonl<'a, W: Write>(
+    out: &mut W,
+    records: impl IntoIterator<Item = ExportRecord<'a>>,
+) -> Result<(), serde_json::Error> {
+    for record in records {
+        serde_json::to_writer(&mut *out, &record)?;
+        writeln!(out).map_err(serde_json::Error::io)?;
+    }
+
+    Ok(())
+}
+
+/// Sums up the duration of records per day of their start
+fn daily_totals<'a>(
+    records: impl Iterator<Item = &'a TimerRecord>,
+    day_boundary: DayBoundary,
+) -> BTreeMap<NaiveDate, Duration> {
+    let mut totals = BTreeMap::new();
+
+    for record in records {
+        let day = day_boundary.day_of(record.start);
+        *totals.entry(day).or_default() += record.duration();
+    }
+
+    totals
+}
+
+/// Sums up the duration of records per week, keyed by the first day of the week
+fn weekly_totals<'a>(
+    records: impl Iterator<Item = &'a TimerRecord>,
+    day_boundary: DayBoundary,
+    start_of_week: StartOfWeek,
+) -> BTreeMap<NaiveDate, Duration> {
+    let mut totals = BTreeMap::new();
+
+    for (day, duration) in daily_totals(records, day_boundary) {
+        *totals.entry(start_of_week.week_start(day)).or_default() += duration;
+    }
+
+    totals
+}
+
+/// Sums up the duration of records per year and month
+fn monthly_totals<'a>(
+    records: impl Iterator<Item = &'a TimerRecord>,
+    day_boundary: DayBoundary,
+) -> BTreeMap<(i32, u32), Duration> {
+    let mut totals = BTreeMap::new();
+
+    for (day, duration) in daily_totals(records, day_boundary) {
+        *totals.entry((day.year(), day.month())).or_default() += duration;
+    }
+
+    totals
+}
+
+/// Sums up the duration of records per tag. Records with several tags count
+/// towards each of them, untagged records are left out.
+fn tag_totals<'a>(records: impl Iterator<Item = &'a TimerRecord>) -> BTreeMap<&'a str, Duration> {
+    let mut totals = BTreeMap::new();
+
+    for record in records {
+        for tag in &record.tags {
+            *totals.entry(tag.as_str()).or_default() += record.duration();
+        }
+    }
+
+    totals
+}
+
+/// Sums up the duration of records with identical comments
+fn comment_totals<'a>(
+    records: impl Iterator<Item = &'a TimerRecord>,
+) -> BTreeMap<&'a str, Duration> {
+    let mut totals = BTreeMap::new();
+
+    for record in records {
+        *totals.entry(record.comment.as_str()).or_default() += record.duration();
+    }
+
+    totals
+}
+
+/// Groups records as requested for `report`, returning labeled totals in display order
+fn grouped_totals(
+    records: &[&TimerRecord],
+    group_by: GroupBy,
+    day_boundary: DayBoundary,
+    start_of_week: StartOfWeek,
+) -> Vec<(String, Duration)> {
+    let records = records.iter().copied();
+
+    let label = |day: NaiveDate| period_label(group_by, day, start_of_week).unwrap_or_default();
+
+    match group_by {
+        GroupBy::Day => daily_totals(records, day_boundary)
+            .into_iter()
+            .map(|(day, duration)| (label(day), duration))
+            .collect(),
+        GroupBy::Week => weekly_totals(records, day_boundary, start_of_week)
+            .into_iter()
+            .map(|(week_start, duration)| (label(week_start), duration))
+            .collect(),
+        GroupBy::Month => monthly_totals(records, day_boundary)
+            .into_iter()
+            .map(|((year, month), duration)| (label(NaiveDate::from_ymd(year, month, 1)), duration))
+            .collect(),
+        GroupBy::Tag => tag_totals(records)
+            .into_iter()
+            .map(|(tag, duration)| (tag.to_owned(), duration))
+            .collect(),
+        GroupBy::Comment => comment_totals(records)
+            .into_iter()
+            .map(|(comment, duration)| {
+                let label = if comment.is_empty() {
+                    "(no comment)"
+                } else {
+                    comment
+                };
+                (label.to_owned(), duration)
+            })
+            .collect(),
+    }
+}
+
+/// The label of the day, week or month group that `day` belongs to, `None`
+/// when grouping by something other than time
+fn period_label(group_by: GroupBy, day: NaiveDate, start_of_week: StartOfWeek) -> Option<String> {
+    match group_by {
+        GroupBy::Day => Some(day.to_string()),
+        GroupBy::Week => Some(format!("Week of {}", start_of_week.week_start(day))),
+        GroupBy::Month => Some(format!("{}-{:02}", day.year(), day.month())),
+        GroupBy::Tag | GroupBy::Comment => None,
+    }
+}
+
+/// Adds `adjustments` to the groups of `grouped_totals` for the period they
+/// were made in. Adjustments carry no tags and aren't records, so grouping by
+/// tag or comment leaves them out, while the overall totals include them.
+fn add_adjustments(
+    groups: &mut Vec<(String, Duration)>,
+    adjustments: &[&Adjustment],
+    group_by: GroupBy,
+    day_boundary: DayBoundary,
+    start_of_week: StartOfWeek,
+) {
+    for adjustment in adjustments {
+        let day = day_boundary.day_of(adjustment.time);
+        let label = match period_label(group_by, day, start_of_week) {
+            Some(label) => label,
+            None => return,
+        };
+
+        // period labels sort like the periods themselves
+        match groups.binary_search_by(|(group, _)| group.as_str().cmp(&label)) {
+            Ok(index) => groups[index].1 = adjust_duration(groups[index].1, adjustment.seconds),
+            Err(index) if adjustment.seconds > 0 => groups.insert(
+                index,
+                (label, adjust_duration(Duration::ZERO, adjustment.seconds)),
+            ),
+            Err(_) => {}
+        }
+    }
+}
+
+/// Counts the records in each group of `grouped_totals`, a record tagged
+/// several times counts once for each tag
+fn grouped_counts(
+    records: &[&TimerRecord],
+    group_by: GroupBy,
+    day_boundary: DayBoundary,
+    start_of_week: StartOfWeek,
+) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for &record in records {
+        for (label, _) in grouped_totals(&[record], group_by, day_boundary, start_of_week) {
+            *counts.entry(label).or_insert(0) += 1;
+        }
+    }
+
+    counts
+}
+
+/// Renders rows as columns padded to their widest cell, the first column
+/// aligned left and the others right. The footer is set off by a rule.
+fn render_table(header: &[&str], rows: &[Vec<String>], footer: Option<&[String]>) -> String {
+    let header: Vec<String> = header.iter().map(|&cell| cell.to_owned()).collect();
+    let mut widths = vec![0; header.len()];
+    let all_rows = std::iter::once(header.as_slice())
+        .chain(rows.iter().map(Vec::as_slice))
+        .chain(footer);
+    for row in all_rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let render_row = |row: &[String]| {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(column, (cell, &width))| match column {
+                0 => format!("{:<width$}", cell, width = width),
+                _ => format!("{:>width$}", cell, width = width),
+            })
+            .collect();
+        let mut line = cells.join("  ").trim_end().to_owned();
+        line.push('\n');
+        line
+    };
+
+    let mut output = render_row(&header);
+    for row in rows {
+        output.push_str(&render_row(row));
+    }
+    if let Some(footer) = footer {
+        let rule_width = widths.iter().sum::<usize>() + 2 * widths.len().saturating_sub(1);
+        output.push_str(&"-".repeat(rule_width));
+        output.push('\n');
+        output.push_str(&render_row(footer));
+    }
+
+    output
+}
+
+/// Renders the groups of a report with their durations and record counts,
+/// with the overall total in the footer
+fn render_report_table(
+    group_by: GroupBy,
+    groups: &[(String, Duration)],
+    counts: &HashMap<String, usize>,
+    total: Duration,
+) -> String {
+    let count_of = |label: &String| counts.get(label).copied().unwrap_or_default();
+    let rows: Vec<Vec<String>> = groups
+        .iter()
+        .map(|(label, duration)| {
+            vec![
+                label.clone(),
+                get_duration_string(duration),
+                count_of(label).to_string(),
+            ]
+        })
+        .collect();
+    let record_count: usize = groups.iter().map(|(label, _)| count_of(label)).sum();
+    let footer = [
+        "Total".to_owned(),
+        get_duration_string(&total),
+        record_count.to_string(),
+    ];
+
+    render_table(
+        &[group_by.column_name(), "Duration", "Records"],
+        &rows,
+        Some(&footer),
+    )
+}
+
+/// Rounds a duration up to the next multiple of `unit`
+fn round_duration(duration: Duration, unit: Duration) -> Duration {
+    if unit.is_zero() {
+        return duration;
+    }
+
+    let units = duration.as_nanos().div_ceil(unit.as_nanos());
+    Duration::from_nanos((units * unit.as_nanos()) as u64)
+}
+
+/// Extends the first or last record of each day to at least `minimum`,
+/// leaving the other records alone
+fn apply_daily_minimum(
+    records: &[&TimerRecord],
+    minimum: Duration,
+    minimum_on: MinimumOn,
+    day_boundary: DayBoundary,
+) -> Vec<TimerRecord> {
+    let mut billed: Vec<TimerRecord> = records.iter().map(|&record| record.clone()).collect();
+
+    let mut days: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+    for (index, record) in billed.iter().enumerate() {
+        let day = day_boundary.day_of(record.start);
+        let picked = days.entry(day).or_insert(index);
+        let replace = match minimum_on {
+            MinimumOn::First => record.start < billed[*picked].start,
+            MinimumOn::Last => record.start > billed[*picked].start,
+        };
+        if replace {
+            *picked = index;
+        }
+    }
+
+    for index in days.into_values() {
+        let record = &mut billed[index];
+        if record.duration() < minimum {
+            record.end = record.start
+                + chrono::Duration::from_std(minimum).unwrap_or_else(|_| chrono::Duration::zero());
+        }
+    }
+
+    billed
+}
+
+/// Computes the grouped totals and the overall total shown by `report`. With
+/// `RoundScope::Record` every record is rounded before summing, with
+/// `RoundScope::Total` each group total and the overall total are rounded once.
+/// Adjustments are added unrounded before the rounding of the totals.
+fn report_totals(
+    records: &[&TimerRecord],
+    adjustments: &[&Adjustment],
+    group_by: GroupBy,
+    day_boundary: DayBoundary,
+    start_of_week: StartOfWeek,
+    round: Option<(Duration, RoundScope)>,
+) -> (Vec<(String, Duration)>, Duration) {
+    let rounded_records: Vec<TimerRecord>;
+    let mut records = records.to_vec();
+
+    if let Some((unit, RoundScope::Record)) = round {
+        rounded_records = records
+            .iter()
+            .map(|&record| {
+                let mut rounded = record.clone();
+                rounded.end = record.start
+                    + chrono::Duration::from_std(round_duration(record.duration(), unit))
+                        .unwrap_or_else(|_| chrono::Duration::zero());
+                rounded
+            })
+            .collect();
+        records = rounded_records.iter().collect();
+    }
+
+    let mut groups = grouped_totals(&records, group_by, day_boundary, start_of_week);
+    add_adjustments(
+        &mut groups,
+        adjustments,
+        group_by,
+        day_boundary,
+        start_of_week,
+    );
+    let adjustment = adjustments.iter().map(|adjustment| adjustment.seconds);
+    let recorded = records.iter().map(|record| record.duration());
+    let mut total = adjust_duration(recorded.sum(), adjustment.sum());
+
+    if let Some((unit, RoundScope::Total)) = round {
+        for (_, duration) in &mut groups {
+            *duration = round_duration(*duration, unit);
+        }
+        total = round_duration(total, unit);
+    }
+
+    (groups, total)
+}
+
+/// Renders this week's total next to last week's, with the change in between
+fn render_week_comparison(this_week: Duration, last_week: Duration) -> String {
+    let (sign, delta) = if this_week >= last_week {
+        ('+', this_week - last_week)
+    } else {
+        ('-', last_week - this_week)
+    };
+    let percentage = if last_week.is_zero() {
+        "n/a".to_owned()
+    } else {
+        let change = (this_week.as_secs_f64() / last_week.as_secs_f64() - 1.0) * 100.0;
+        format!("{:+.0}%", change)
+    };
+
+    format!(
+        "This week: {}\nLast week: {}\nChange:    {}{} ({})\n",
+        get_duration_string(&this_week),
+        get_duration_string(&last_week),
+        sign,
+        get_duration_string(&delta),
+        percentage
+    )
+}
+
+/// Parses a month given as `YYYY-MM` into its first day
+fn parse_month(input: &str) -> Result<NaiveDate, AppError> {
+    NaiveDate::parse_from_str(&format!("{}-01", input.trim()), "%Y-%m-%d")
+        .map_err(|_| AppError::InvalidMonth(input.to_owned()))
+}
+
+/// A terminal foreground color
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TimerColor {
+    /// Index into the 256 color palette
+    Palette(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl TimerColor {
+    /// Parses a basic color name or a `#rrggbb` hex code
+    fn parse(input: &str) -> Result<Self, AppError> {
+        const NAMES: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+
+        let input = input.trim();
+        if let Some(index) = NAMES
+            .iter()
+            .position(|name| input.eq_ignore_ascii_case(name))
+        {
+            return Ok(TimerColor::Palette(index as u8));
+        }
+
+        let invalid = || AppError::InvalidColor(input.to_owned());
+        let hex = input.strip_prefix('#').ok_or_else(invalid)?;
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+
+        Ok(TimerColor::Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    /// Picks a color from a fixed set based on `name`, so a timer without a
+    /// color of its own always gets the same one
+    fn from_name(name: &str) -> Self {
+        // well distinguishable colors of the 256 color palette
+        const COLORS: [u8; 10] = [33, 39, 71, 107, 141, 167, 173, 208, 213, 220];
+
+        // FNV-1a, unlike std's hashers guaranteed to stay the same across Rust versions
+        let hash = name.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+
+        TimerColor::Palette(COLORS[(hash % COLORS.len() as u64) as usize])
+    }
+
+    /// The color of a timer, the automatic one if it has none or an invalid one
+    fn of_timer(name: &str, timer: &Timer) -> Self {
+        timer
+            .color
+            .as_deref()
+            .and_then(|color| TimerColor::parse(color).ok())
+            .unwrap_or_else(|| TimerColor::from_name(name))
+    }
+
+    fn paint(self, text: &str) -> String {
+        match self {
+            TimerColor::Palette(index) => format!("\x1b[38;5;{}m{}\x1b[0m", index, text),
+            TimerColor::Rgb(r, g, b) => format!("\x1b[38;2;{};{};{}m{}\x1b[0m", r, g, b, text),
+        }
+    }
+}
+
+/// Maps durations to bars of a unicode sparkline, scaled to the largest one
+fn sparkline(durations: &[Duration]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let max = durations.iter().max().copied().unwrap_or_default();
+
+    durations
+        .iter()
+        .map(|duration| {
+            if max.is_zero() {
+                return BARS[0];
+            }
+
+            let ratio = duration.as_secs_f64() / max.as_secs_f64();
+            BARS[(ratio * (BARS.len() - 1) as f64).round() as usize]
+        })
+        .collect()
+}
+
+/// This week's tracked time of `timer`, including a running interval, and
+/// the share of its weekly goal that it makes up. `None` without a goal.
+fn goal_progress(
+    timer: &Timer,
+    start_of_week: StartOfWeek,
+    now: DateTime<Utc>,
+) -> Option<(Duration, f64)> {
+    let goal = timer.weekly_goal.filter(|goal| !goal.is_zero())?;
+    let week_start = start_of_week.week_start(DayBoundary::Local.day_of(now));
+    let week_end = week_start + chrono::Duration::weeks(1);
+    let (start, end) = (local_midnight(week_start), local_midnight(week_end));
+    let done = adjust_duration(
+        records_duration_in_range(timer.records_with_running(now).iter(), start, end, false),
+        timer.adjustment_seconds(start, end),
+    );
+
+    Some((done, done.as_secs_f64() / goal.as_secs_f64()))
+}
+
+/// A bar of `width` cells filled to `fraction`, capped at full
+fn progress_bar(fraction: f64, width: usize) -> String {
+    let filled = ((fraction.clamp(0.0, 1.0) * width as f64).round() as usize).min(width);
+
+    format!("{}{}", "█".repeat(filled), "░".repeat(width - filled))
+}
+
+/// The goal line of `show`, or `None` if the timer has no weekly goal
+fn render_goal_progress(
+    timer: &Timer,
+    start_of_week: StartOfWeek,
+    now: DateTime<Utc>,
+) -> Option<String> {
+    let (done, fraction) = goal_progress(timer, start_of_week, now)?;
+
+    Some(format!(
+        "Weekly goal: {} of {} ({:.0}%) {}",
+        get_duration_string(&done),
+        get_duration_string(&timer.weekly_goal?),
+        fraction * 100.0,
+        progress_bar(fraction, 10)
+    ))
+}
+
+const HEATMAP_LEVELS: usize = 5;
+
+/// Maps a day's duration to a heatmap intensity level between 0 (nothing
+/// tracked) and `HEATMAP_LEVELS - 1` (the maximum in range)
+fn heatmap_level(duration: Duration, max: Duration) -> usize {
+    if duration.is_zero() || max.is_zero() {
+        return 0;
+    }
+
+    let steps = (HEATMAP_LEVELS - 1) as f64;
+    let level = (duration.as_secs_f64() / max.as_secs_f64() * steps).ceil() as usize;
+
+    level.clamp(1, HEATMAP_LEVELS - 1)
+}
+
+/// Renders a calendar of the month starting at `first_day`, with each day
+/// shaded by its tracked time relative to the busiest day of the month
+fn render_heatmap(
+    totals: &BTreeMap<NaiveDate, Duration>,
+    first_day: NaiveDate,
+    start_of_week: StartOfWeek,
+    color: bool,
+) -> String {
+    // green shades of the 256 color palette
+    const COLORS: [u8; HEATMAP_LEVELS] = [237, 22, 28, 34, 46];
+    const DENSITY: [&str; HEATMAP_LEVELS] = ["··", "░░", "▒▒", "▓▓", "██"];
+
+    let days: Vec<NaiveDate> = first_day
+        .iter_days()
+        .take_while(|day| day.month() == first_day.month())
+        .collect();
+    let max = days
+        .iter()
+        .filter_map(|day| totals.get(day))
+        .max()
+        .copied()
+        .unwrap_or_default();
+
+    let mut output = format!(
+        "{}\n{}\n",
+        first_day.format("%B %Y"),
+        start_of_week.weekday_header()
+    );
+    output.push_str(&"   ".repeat(start_of_week.days_into_week(first_day) as usize));
+
+    for day in days {
+        let level = heatmap_level(totals.get(&day).copied().unwrap_or_default(), max);
+
+        if color {
+            output.push_str(&format!("\x1b[38;5;{}m██\x1b[0m", COLORS[level]));
+        } else {
+            output.push_str(DENSITY[level]);
+        }
+
+        output.push(if start_of_week.days_into_week(day) == 6 {
+            '\n'
+        } else {
+            ' '
+        });
+    }
+
+    format!("{}\n", output.trim_end())
+}
+
+/// Decides which record comments a search matches
+enum CommentMatcher {
+    Substring { needle: String, ignore_case: bool },
+    Regex(regex::Regex),
+}
+
+impl CommentMatcher {
+    fn new(query: &str, regex: bool, ignore_case: bool) -> Result<Self, AppError> {
+        if regex {
+            let regex = regex::RegexBuilder::new(query)
+                .case_insensitive(ignore_case)
+                .build()
+                .map_err(|err| AppError::InvalidRegex(err.to_string()))?;
+
+            Ok(CommentMatcher::Regex(regex))
+        } else {
+            let needle = if ignore_case {
+                query.to_lowercase()
+            } else {
+                query.to_owned()
+            };
+
+            Ok(CommentMatcher::Substring {
+                needle,
+                ignore_case,
+            })
+        }
+    }
+
+    fn is_match(&self, comment: &str) -> bool {
+        match self {
+            CommentMatcher::Substring {
+                needle,
+                ignore_case: true,
+            } => comment.to_lowercase().contains(needle.as_str()),
+            CommentMatcher::Substring { needle, .. } => comment.contains(needle.as_str()),
+            CommentMatcher::Regex(regex) => regex.is_match(comment),
+        }
+    }
+
+    /// Byte ranges of the non-empty matches in `comment`
+    fn find_ranges(&self, comment: &str) -> Vec<std::ops::Range<usize>> {
+        let ranges: Vec<std::ops::Range<usize>> = match self {
+            CommentMatcher::Substring {
+                needle,
+                ignore_case: true,
+            } => regex::RegexBuilder::new(&regex::escape(needle))
+                .case_insensitive(true)
+                .build()
+                .map(|regex| regex.find_iter(comment).map(|m| m.range()).collect())
+                .unwrap_or_default(),
+            CommentMatcher::Substring { needle, .. } => comment
+                .match_indices(needle.as_str())
+                .map(|(start, found)| start..start + found.len())
+                .collect(),
+            CommentMatcher::Regex(regex) => regex.find_iter(comment).map(|m| m.range()).collect(),
+        };
+
+        ranges
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .collect()
+    }
+}
+
+/// Wraps the given byte ranges of `text` in bold red, like grep does
+fn highlight_ranges(text: &str, ranges: &[std::ops::Range<usize>]) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut last = 0;
+    for range in ranges {
+        output.push_str(&text[last..range.start]);
+        output.push_str(&format!("\x1b[1;31m{}\x1b[0m", &text[range.clone()]));
+        last = range.end;
+    }
+    output.push_str(&text[last..]);
+
+    output
+}
+
+/// A comment matcher, optionally negated to find the records NOT matching
+struct SearchPredicate {
+    matcher: CommentMatcher,
+    invert: bool,
+    /// Records outside the range never match, not even with `invert`
+    filter: RecordFilter,
+}
+
+impl SearchPredicate {
+    fn matches(&self, record: &TimerRecord) -> bool {
+        self.filter.matches(record) && self.matcher.is_match(&record.comment) != self.invert
+    }
+}
+
+/// Records that started longer ago are shown with absolute times even with `records --relative`
+const RELATIVE_TIME_LIMIT: Duration = Duration::from_secs(24 * 3600);
+
+/// Describes `time` relative to `now` by its largest unit, like `5m ago`
+fn relative_time(time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    match (now - time).to_std() {
+        Ok(elapsed) => format!("{} ago", get_capped_duration_string(&elapsed, Some(1))),
+        Err(_) => {
+            let ahead = (time - now).to_std().unwrap_or_default();
+            format!("in {}", get_capped_duration_string(&ahead, Some(1)))
+        }
+    }
+}
+
+/// Shortens `text` to at most `width` characters, ending it with an ellipsis
+/// if anything was cut off
+fn truncate_ellipsis(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_owned();
+    }
+
+    let mut truncated: String = text.chars().take(width.saturating_sub(1)).collect();
+    if width > 0 {
+        truncated.push('…');
+    }
+
+    truncated
+}
+
+/// Breaks `text` into lines of at most `width` characters at spaces,
+/// splitting words only if they don't fit on a line of their own
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines: Vec<String> = vec![];
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+
+        while !word.is_empty() {
+            let line_len = line.chars().count();
+            let needed = if line.is_empty() {
+                word.len()
+            } else {
+                line_len + 1 + word.len()
+            };
+
+            if needed <= width {
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.extend(word.drain(..));
+            } else if line.is_empty() {
+                line.extend(word.drain(..width));
+                lines.push(std::mem::take(&mut line));
+            } else {
+                lines.push(std::mem::take(&mut line));
+            }
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Whole seconds of the total of `timer`, optionally with the running interval
+fn total_seconds(timer: &Timer, include_running: bool, now: DateTime<Utc>) -> u64 {
+    let running = timer
+        .current_elapsed(now)
+        .filter(|_| include_running)
+        .unwrap_or_default();
+
+    (timer.total_duration() + running).as_secs()
+}
+
+/// One line with the total of `timer` including the running interval, like
+/// `proj: 1h 20m (running)`
+fn render_short(name: &str, timer: &Timer, max_units: Option<usize>, now: DateTime<Utc>) -> String {
+    let total = Duration::from_secs(total_seconds(timer, true, now));
+
+    format!(
+        "{}: {}{}",
+        name,
+        get_capped_duration_string(&total, max_units),
+        if timer.is_running() { " (running)" } else { "" }
+    )
+}
+
+/// The latest `count` records of `timer` with their indices, oldest first or
+/// newest first with `reverse`
+fn recent_records(timer: &Timer, count: usize, reverse: bool) -> Vec<(usize, &TimerRecord)> {
+    let mut records: Vec<(usize, &TimerRecord)> = timer.records.iter().enumerate().collect();
+    records.sort_by_key(|(_, record)| record.start);
+
+    let mut recent = records.split_off(records.len().saturating_sub(count));
+    if reverse {
+        recent.reverse();
+    }
+
+    recent
+}
+
+/// Formats a record for listings. Given `relative_to`, recent start times
+/// are shown relative to it. Given `comment_width`, the comment is wrapped
+/// with continuation lines indented under its first line.
+fn format_record_line(
+    index: usize,
+    record: &TimerRecord,
+    relative_to: Option<DateTime<Utc>>,
+    comment_width: Option<usize>,
+) -> String {
+    let prefix = record_line_prefix(index, record, relative_to);
+    if record.comment.is_empty() {
+        return prefix.trim_end().to_owned();
+    }
+
+    match comment_width {
+        Some(width) => {
+            let indent = format!("\n{}", " ".repeat(prefix.chars().count()));
+            prefix + &wrap_text(&record.comment, width).join(&indent)
+        }
+        None => prefix + &record.comment,
+    }
+}
+
+/// The index, times and duration in front of the comment in record listings,
+/// padded to the comment column
+fn record_line_prefix(
+    index: usize,
+    record: &TimerRecord,
+    relative_to: Option<DateTime<Utc>>,
+) -> String {
+    let start = match relative_to {
+        Some(now) if (now - record.start).to_std().unwrap_or_default() < RELATIVE_TIME_LIMIT => {
+            format!("{:>16}", relative_time(record.start, now))
+        }
+        _ => record
+            .start
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+    };
+
+    format!(
+        "{:>4}  {} - {}  {:<12}  ",
+        index,
+        start,
+        record.end.with_timezone(&Local).format("%H:%M"),
+        get_duration_string(&record.duration()),
+    )
+}
+
+/// Width left for comments in record listings on the terminal, if stdout is one
+fn terminal_comment_width() -> Option<usize> {
+    // index, start and end time, duration and the spaces in between
+    const RECORD_PREFIX_WIDTH: usize = 4 + 2 + 24 + 2 + 12 + 2;
+
+    let term = console::Term::stdout();
+    if !term.is_term() {
+        return None;
+    }
+
+    let (_, columns) = term.size();
+    Some(
+        (columns as usize)
+            .saturating_sub(RECORD_PREFIX_WIDTH)
+            .max(20),
+    )
+}
+
+/// Time worked on one day for one timer at one rate
+#[derive(Debug, PartialEq)]
+struct InvoiceLine {
+    date: NaiveDate,
+    timer: String,
+    hours: f64,
+    rate: f64,
+    subtotal: f64,
+}
+
+#[derive(Debug, PartialEq)]
+struct Invoice {
+    month: NaiveDate,
+    lines: Vec<InvoiceLine>,
+    total: f64,
+}
+
+/// Bills the records of the named timers started in the month beginning at
+/// `first_day`, with a line per day, timer and rate. Each line's amount is
+/// rounded, the total is the sum of the rounded amounts.
+fn compute_invoice(
+    state: &AppState,
+    timer_names: &[&str],
+    first_day: NaiveDate,
+    rounding: CurrencyRounding,
+) -> Invoice {
+    let mut durations: BTreeMap<(NaiveDate, &str, u64), Duration> = BTreeMap::new();
+
+    for &name in timer_names {
+        let timer = match state.get_timer(name) {
+            Some(timer) => timer,
+            None => continue,
+        };
+
+        for record in &timer.records {
+            let date = record.start.with_timezone(&Local).date().naive_local();
+            if (date.year(), date.month()) != (first_day.year(), first_day.month()) {
+                continue;
+            }
+
+            let rate = record.rate_override.or(timer.rate).unwrap_or_default();
+            *durations.entry((date, name, rate.to_bits())).or_default() += record.duration();
+        }
+    }
+
+    let lines: Vec<InvoiceLine> = durations
+        .into_iter()
+        .map(|((date, timer, rate), duration)| {
+            let hours = duration.as_secs_f64() / 3600.0;
+            let rate = f64::from_bits(rate);
+
+            InvoiceLine {
+                date,
+                timer: timer.to_owned(),
+                hours,
+                rate,
+                subtotal: rounding.apply(hours * rate),
+            }
+        })
+        .collect();
+    let total = CurrencyRounding::TwoDecimals.apply(lines.iter().map(|line| line.subtotal).sum());
+
+    Invoice {
+        month: first_day,
+        lines,
+        total,
+    }
+}
+
+/// Renders `invoice` in `format`. Rates and amounts of text and markdown
+/// invoices use the separators of `locale`, CSV invoices are always plain.
+fn render_invoice(
+    invoice: &Invoice,
+    currency: &str,
+    format: InvoiceFormat,
+    locale: Option<NumberLocale>,
+) -> String {
+    let mut output = String::new();
+    let amount = |amount: f64| format_amount(amount, locale);
+
+    match format {
+        InvoiceFormat::Text => {
+            output.push_str(&format!(
+                "Invoice for {}\n\n",
+                invoice.month.format("%B %Y")
+            ));
+            output.push_str(&format!(
+                "{:<10}  {:<20} {:>8} {:>10} {:>12}\n",
+                "Date", "Timer", "Hours", "Rate", "Amount"
+            ));
+            for line in &invoice.lines {
+                output.push_str(&format!(
+                    "{:<10}  {:<20} {:>8.2} {:>10} {:>12}\n",
+                    line.date,
+                    line.timer,
+                    line.hours,
+                    amount(line.rate),
+                    amount(line.subtotal)
+                ));
+            }
+            output.push_str(&format!(
+                "\n{:<10}  {:>53} {}\n",
+                "Total",
+                amount(invoice.total),
+                currency
+            ));
+        }
+        InvoiceFormat::Markdown => {
+            output.push_str(&format!(
+                "# Invoice for {}\n\n",
+                invoice.month.format("%B %Y")
+            ));
+            output.push_str(&format!(
+                "| Date | Timer | Hours | Rate ({0}) | Amount ({0}) |\n",
+                currency
+            ));
+            output.push_str("|------|-------|------:|-----:|-------:|\n");
+            for line in &invoice.lines {
+                output.push_str(&format!(
+                    "| {} | {} | {:.2} | {} | {} |\n",
+                    line.date,
+                    line.timer,
+                    line.hours,
+                    amount(line.rate),
+                    amount(line.subtotal)
+                ));
+            }
+            output.push_str(&format!(
+                "| **Total** | | | | **{}** |\n",
+                amount(invoice.total)
+            ));
+        }
+        InvoiceFormat::Csv => {
+            output.push_str("date,timer,hours,rate,amount,currency\n");
+            for line in &invoice.lines {
+                output.push_str(&format!(
+                    "{},{},{:.2},{:.2},{:.2},{}\n",
+                    line.date,
+                    csv_field(&line.timer),
+                    line.hours,
+                    line.rate,
+                    line.subtotal,
+                    csv_field(currency)
+                ));
+            }
+            output.push_str(&format!(
+                "total,,,,{:.2},{}\n",
+                invoice.total,
+                csv_field(currency)
+            ));
+        }
+    }
+
+    output
+}
+
+fn get_duration_string(duration: &Duration) -> String {
+    let duration_secs = Duration::from_secs(duration.as_secs());
+
+    let formatted = format_duration(duration_secs);
+
+    formatted.to_string()
+}
+
+/// Like `get_duration_string`, but leaves out all but the `max_units` largest units
+fn get_capped_duration_string(duration: &Duration, max_units: Option<usize>) -> String {
+    let formatted = get_duration_string(duration);
+
+    match max_units {
+        Some(max_units) => formatted
+            .split(' ')
+            .take(max_units.max(1))
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => formatted,
+    }
+}
+
+/// Whether `start` may create the named timer if it doesn't exist yet.
+/// Both `--no-create` and the `no_create` config option take precedence over `--create`.
+fn may_create_timer(cmd: &StartCommand, config: &Config) -> bool {
+    cmd.create && !cmd.no_create && !config.no_create
+}
+
+/// Determines the timer `start`/`stop` act on. Without a name and without an
+/// active timer, the only existing timer is used, or `select` is asked to pick
+/// one of several. `None` means the active timer is used.
+fn resolve_timer_name<F>(
+    state: &AppState,
+    name: Option<&str>,
+    select: F,
+) -> Result<Option<String>, AppError>
+where
+    F: FnOnce(&[&String]) -> Option<usize>,
+{
+    if let Some(name) = name {
+        return Ok(Some(name.to_owned()));
+    }
+    if state.active_timer_name().is_some() {
+        return Ok(None);
+    }
+
+    let names = state.timer_names_sorted();
+
+    match names.len() {
+        0 => Err(AppError::NoSuchTimer),
+        1 => Ok(Some(names[0].clone())),
+        _ => select(&names)
+            .and_then(|index| names.get(index))
+            .map(|name| Some(name.to_string()))
+            .ok_or(AppError::AmbiguousTimer),
+    }
+}
+
+/// Shows a menu to pick a timer, if stdin and stderr are attached to a terminal
+fn select_timer_interactively(names: &[&String]) -> Option<usize> {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return None;
+    }
+
+    dialoguer::Select::new()
+        .with_prompt("Timer")
+        .items(names)
+        .default(0)
+        .interact_opt()
+        .ok()
+        .flatten()
+}
+
+fn start(
+    state: &mut AppState,
+    cmd: &StartCommand,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    if state.is_running() && !cmd.force {
+        return Err(AppError::TimerAlreadyRunning);
+    }
+
+    let timer_name =
+        resolve_timer_name(state, cmd.timer_name.as_deref(), select_timer_interactively)?;
+    if let Some(timer_name) = &timer_name {
+        if may_create_timer(cmd, config) {
+            state.create_timer(timer_name);
+        }
+        if state.get_timer(timer_name).is_none() {
+            return Err(AppError::NoSuchTimer);
+        }
+    }
+
+    // only reached with --force
+    if state.is_running() {
+        let running = state.get_active_timer_mut().unwrap();
+        let record = running.stop_timer(now, cmd.comment.clone().unwrap_or_default())?;
+
+        println!(
+            "Stopped running timer after {}",
+            get_duration_string(&record.duration())
+        );
+    }
+
+    if let Some(timer_name) = timer_name {
+        state.set_timer_active(&timer_name)?;
+    }
+
+    let timer = state.get_active_timer_mut().ok_or(AppError::NoSuchTimer)?;
+    timer.start_timer(now)?;
+    timer.current_comment = cmd.comment.clone();
+
+    println!("Timer started");
+
+    Ok(())
+}
+
+/// Builds the comment of a stopped record from the comment pending since
+/// `start`. `replace` takes the place of the pending comment, `append` is
+/// appended afterwards, so with both given the result is `replace` + `append`.
+fn stop_comment(pending: Option<&str>, replace: Option<&str>, append: Option<&str>) -> String {
+    let comment = replace.or(pending).unwrap_or_default();
+
+    match append {
+        Some(append) if comment.is_empty() => append.to_owned(),
+        Some(append) => format!("{} {}", comment, append),
+        None => comment.to_owned(),
+    }
+}
+
+/// Fills in the `{date}`, `{timer}` and `{duration}` placeholders of a comment template
+fn expand_comment_template(template: &str, timer_name: &str, record: &TimerRecord) -> String {
+    let date = record.start.with_timezone(&Local).format("%Y-%m-%d");
+
+    template
+        .replace("{date}", &date.to_string())
+        .replace("{timer}", timer_name)
+        .replace("{duration}", &get_duration_string(&record.duration()))
+}
+
+/// Stops the running timer. A named timer is only stopped if it is the one
+/// running, stopping any other timer fails with `TimerNotRunning` and leaves
+/// the running timer untouched.
+fn stop(
+    state: &mut AppState,
+    cmd: &StopCommand,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let stop_time = match &cmd.stop_time {
+        Some(stop_time) => parse_time(stop_time, now)?,
+        None => now,
+    };
+
+    if cmd.all {
+        let running: Vec<String> = state
+            .timer_names_sorted()
+            .into_iter()
+            .filter(|name| state.timers[*name].is_running())
+            .cloned()
+            .collect();
+        for name in &running {
+            let timer = state.get_timer_mut(name).ok_or(AppError::NoSuchTimer)?;
+            stop_running_timer(timer, name, cmd, config, stop_time)?;
+        }
+        println!("Stopped {} timer(s)", running.len());
+
+        return Ok(());
+    }
+
+    let timer_name =
+        resolve_timer_name(state, cmd.timer_name.as_deref(), select_timer_interactively)?
+            .map(|name| state.canonical_name(&name).to_owned());
+    let name = timer_name
+        .clone()
+        .or_else(|| state.active_timer_name().map(str::to_owned))
+        .unwrap_or_default();
+
+    let timer = match &timer_name {
+        Some(timer_name) => {
+            let timer = state
+                .get_timer_mut(timer_name)
+                .ok_or(AppError::NoSuchTimer)?;
+            if !timer.is_running() {
+                return Err(AppError::TimerNotRunning(timer_name.clone()));
+            }
+            timer
+        }
+        None => state
+            .get_active_timer_mut()
+            .ok_or(AppError::NoTimerRunning)?,
+    };
+
+    stop_running_timer(timer, &name, cmd, config, stop_time)
+}
+
+/// Stops `timer` named `name` as configured by `cmd` and `config`
+fn stop_running_timer(
+    timer: &mut Timer,
+    name: &str,
+    cmd: &StopCommand,
+    config: &Config,
+    stop_time: DateTime<Utc>,
+) -> Result<(), AppError> {
+    if timer.current_start.is_some_and(|start| stop_time < start) {
+        return Err(AppError::EndBeforeStart);
+    }
+    if let Some(threshold) = cmd.discard_if_shorter {
+        let elapsed = timer.current_elapsed(stop_time).unwrap_or_default();
+        if elapsed < threshold {
+            timer.abort_timer()?;
+            println!(
+                "Timer discarded after {}, shorter than {}",
+                get_duration_string(&elapsed),
+                get_duration_string(&threshold)
+            );
+            return Ok(());
+        }
+    }
+
+    let given = match &cmd.comment_from_file {
+        Some(path) => Some(read_comment_file(path)?),
+        None => cmd.comment.clone(),
+    };
+    let mut comment = stop_comment(
+        timer.current_comment.as_deref(),
+        given.as_deref(),
+        cmd.append_comment.as_deref(),
+    );
+    let template = cmd
+        .comment_template
+        .as_deref()
+        .or(config.comment_template.as_deref());
+    if comment.is_empty() {
+        comment = match (template, timer.current_start) {
+            (Some(template), Some(start)) => {
+                let record = TimerRecord::new(start, stop_time, String::new());
+                expand_comment_template(template, name, &record)
+            }
+            _ => config.comment_default.clone().unwrap_or_default(),
+        };
+    }
+    if (cmd.comment_required || config.comment_required) && comment.trim().is_empty() {
+        return Err(AppError::CommentRequired);
+    }
+
+    let record = if cmd.keep_running {
+        timer.checkpoint(stop_time, comment)?
+    } else {
+        timer.stop_timer(stop_time, comment)?
+    };
+    record.rate_override = cmd.rate;
+    let record = record.clone();
+
+    if cmd.keep_running {
+        println!(
+            "Recorded {} so far, timer keeps running\nTotal: {}",
+            get_duration_string(&record.duration()),
+            get_duration_string(&timer.total_duration())
+        );
+    } else {
+        println!("{}", stop_message(&record, timer.total_duration()));
+    }
+
+    Ok(())
+}
+
+/// Reads a record comment from `path`, without the trailing newline
+fn read_comment_file(path: &Path) -> Result<String, AppError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| AppError::CannotReadComment(path.to_path_buf(), err.kind()))?;
+    Ok(contents.trim_end().to_owned())
+}
+
+/// Confirmation of `stop`, with the duration of the new record and the new
+/// total of its timer
+fn stop_message(record: &TimerRecord, total: Duration) -> String {
+    format!(
+        "Timer stopped after {}\nTotal: {}",
+        get_duration_string(&record.duration()),
+        get_duration_string(&total)
+    )
+}
+
+fn abort(state: &mut AppState) -> Result<(), AppError> {
+    let timer = state
+        .get_active_timer_mut()
+        .ok_or(AppError::NoTimerRunning)?;
+    timer.abort_timer()?;
+
+    println!("Timer aborted");
+
+    Ok(())
+}
+
+fn show(
+    state: &AppState,
+    cmd: &ShowCommand,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<(), Box<dyn Error>> {
+    let (name, timer) = state.resolve_timer(cmd.timer_name.as_deref())?;
+    let max_units = cmd.max_units.or(config.max_units);
+
+    if cmd.json || cmd.json_pretty {
+        println!(
+            "{}",
+            json_string(&TimerSummary::new(name, timer, now), cmd.json_pretty)?
+        );
+        return Ok(());
+    }
+
+    if cmd.duration_only {
+        println!("{}", total_seconds(timer, !cmd.exclude_running, now));
+        return Ok(());
+    }
+
+    if cmd.short {
+        println!("{}", render_short(name, timer, max_units, now));
+        return Ok(());
+    }
+
+    if let Some(description) = &timer.description {
+        println!("{}: {}", name, description);
+    }
+    if let Some(current) = timer.record_preview(now) {
+        println!(
+            "Current timer: {}",
+            get_capped_duration_string(&current.duration(), max_units)
+        );
+    }
+    println!(
+        "Total: {}",
+        get_capped_duration_string(&timer.total_duration(), max_units)
+    );
+    if timer.rate.is_some() || timer.records.iter().any(|r| r.rate_override.is_some()) {
+        println!(
+            "Cost: {}",
+            format_amount(timer.cost(cmd.round_currency), cmd.locale.or(config.locale))
+        );
+    }
+    if cmd.goal || config.show_goal {
+        if let Some(goal) = render_goal_progress(timer, config.start_of_week, now) {
+            println!("{}", goal);
+        }
+    }
+    if let Some(count) = cmd.recent {
+        for (index, record) in recent_records(timer, count, cmd.reverse) {
+            println!("{}", format_record_line(index, record, None, None));
+        }
+    }
+    if cmd.chart {
+        let today = DayBoundary::Local.day_of(now);
+        let durations: Vec<Duration> = (0..cmd.days)
+            .rev()
+            .map(|days_ago| today - chrono::Duration::days(days_ago.into()))
+            .map(|day| {
+                timer.duration_in_range(local_midnight(day), local_midnight(day.succ()), false)
+            })
+            .collect();
+
+        println!("Last {} days: {}", cmd.days, sparkline(&durations));
+    }
+
+    Ok(())
+}
+
+/// Overview of a timer as printed by `show --json` and `list --json`
+#[derive(Serialize)]
+struct TimerSummary<'a> {
+    name: &'a str,
+    description: Option<&'a str>,
+    running: bool,
+    current_seconds: Option<u64>,
+    current: Option<TimerRecordView<'a>>,
+    total_seconds: u64,
+    record_count: usize,
+    pinned: bool,
+}
+
+impl<'a> TimerSummary<'a> {
+    fn new(name: &'a str, timer: &'a Timer, now: DateTime<Utc>) -> Self {
+        Self {
+            name,
+            description: timer.description.as_deref(),
+            running: timer.is_running(),
+            current_seconds: timer.current_elapsed(now).map(|elapsed| elapsed.as_secs()),
+            current: timer.record_preview(now),
+            total_seconds: timer.total_duration().as_secs(),
+            record_count: timer.records_len(),
+            pinned: timer.pinned,
+        }
+    }
+}
+
+/// Orders two timers by `sort`, falling back to their names for equal keys
+fn compare_timers(sort: ListSort, a: (&String, &Timer), b: (&String, &Timer)) -> Ordering {
+    let (a_name, a) = a;
+    let (b_name, b) = b;
+
+    let ordering = match sort {
+        ListSort::Name => Ordering::Equal,
+        ListSort::Total => b.total_duration().cmp(&a.total_duration()),
+        ListSort::Records => b.records_len().cmp(&a.records_len()),
+        ListSort::Recent => b.last_activity().cmp(&a.last_activity()),
+    };
+
+    ordering.then_with(|| a_name.cmp(b_name))
+}
+
+fn sorted_timers(state: &AppState, sort: ListSort, reverse: bool) -> Vec<(&String, &Timer)> {
+    let mut timers: Vec<(&String, &Timer)> = state
+        .timer_names_sorted()
+        .into_iter()
+        .map(|name| (name, &state.timers[name]))
+        .collect();
+    // pinned timers stay on top, also in reverse order
+    timers.sort_by(|&a, &b| {
+        let ordering = compare_timers(sort, a, b);
+        let ordering = if reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+
+        b.1.pinned.cmp(&a.1.pinned).then(ordering)
+    });
+
+    timers
+}
+
+/// The timers shown by `list`, filtered and in display order
+fn listed_timers<'a>(state: &'a AppState, cmd: &ListCommand) -> Vec<(&'a String, &'a Timer)> {
+    let mut timers = sorted_timers(state, cmd.sort, cmd.reverse);
+    timers.retain(|(_, timer)| !cmd.only_running || timer.is_running());
+    timers.retain(|(_, timer)| cmd.all || !timer.is_empty());
+
+    timers
+}
+
+fn render_list(state: &AppState, timers: &[(&String, &Timer)], color: bool) -> String {
+    let mut output = String::new();
+    for &(name, timer) in timers {
+        let marker = if state.active_timer_name() == Some(name.as_str()) {
+            '*'
+        } else {
+            ' '
+        };
+        let mut padded_name = format!("{:<20}", name);
+        if color {
+            padded_name = TimerColor::of_timer(name, timer).paint(&padded_name);
+        }
+
+        let line = format!(
+            "{} {} {:<14} {}",
+            marker,
+            padded_name,
+            get_duration_string(&timer.total_duration()),
+            timer.description.as_deref().unwrap_or_default()
+        );
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+
+    output
+}
+
+fn list(
+    state: &AppState,
+    cmd: &ListCommand,
+    color: bool,
+    now: DateTime<Utc>,
+) -> Result<(), Box<dyn Error>> {
+    let timers = listed_timers(state, cmd);
+
+    if cmd.json || cmd.json_pretty {
+        let summaries: Vec<TimerSummary> = timers
+            .iter()
+            .map(|&(name, timer)| TimerSummary::new(name, timer, now))
+            .collect();
+
+        println!("{}", json_string(&summaries, cmd.json_pretty)?);
+    } else {
+        print!("{}", render_list(state, &timers, color));
+    }
+
+    Ok(())
+}
+
+fn describe(state: &mut AppState, cmd: &DescribeCommand) -> Result<(), AppError> {
+    let timer = state
+        .get_timer_mut(&cmd.timer_name)
+        .ok_or(AppError::NoSuchTimer)?;
+    timer.description = cmd.description.clone();
+
+    Ok(())
+}
+
+fn split(state: &mut AppState, cmd: &SplitCommand, now: DateTime<Utc>) -> Result<(), AppError> {
+    let at = parse_time(&cmd.at, now)?;
+    let timer = state
+        .get_timer_mut(&cmd.timer_name)
+        .ok_or(AppError::NoSuchTimer)?;
+    timer.split_record(cmd.index, at)?;
+
+    if let Some(comment) = &cmd.comment_before {
+        timer.records[cmd.index].comment = comment.clone();
+    }
+    if let Some(comment) = &cmd.comment_after {
+        timer.records[cmd.index + 1].comment = comment.clone();
+    }
+
+    Ok(())
+}
+
+fn set_goal(state: &mut AppState, cmd: &SetGoalCommand) -> Result<(), AppError> {
+    let timer = state
+        .get_timer_mut(&cmd.timer_name)
+        .ok_or(AppError::NoSuchTimer)?;
+    timer.weekly_goal = cmd.weekly_goal;
+
+    Ok(())
+}
+
+fn set_color(state: &mut AppState, cmd: &SetColorCommand) -> Result<(), AppError> {
+    if let Some(color) = &cmd.color {
+        TimerColor::parse(color)?;
+    }
+
+    let timer = state
+        .get_timer_mut(&cmd.timer_name)
+        .ok_or(AppError::NoSuchTimer)?;
+    timer.color = cmd.color.clone();
+
+    Ok(())
+}
+
+fn pin(state: &mut AppState, cmd: &PinCommand, pinned: bool) -> Result<(), AppError> {
+    let timer = state
+        .get_timer_mut(&cmd.timer_name)
+        .ok_or(AppError::NoSuchTimer)?;
+    timer.pinned = pinned;
+
+    Ok(())
+}
+
+/// Defines, removes or lists the aliases of timers
+fn alias(state: &mut AppState, cmd: &AliasCommand) -> Result<(), AppError> {
+    let alias = match &cmd.alias {
+        Some(alias) => alias,
+        None => {
+            let mut aliases: Vec<(&String, &String)> = state.aliases.iter().collect();
+            aliases.sort_unstable();
+            for (alias, timer_name) in aliases {
+                println!("{} -> {}", alias, timer_name);
+            }
+
+            return Ok(());
+        }
+    };
+
+    if cmd.remove {
+        state
+            .aliases
+            .remove(alias)
+            .ok_or_else(|| AppError::NoSuchAlias(alias.clone()))?;
+        println!("Alias {} removed", alias);
+    } else if let Some(timer_name) = &cmd.timer_name {
+        state.set_alias(alias, timer_name)?;
+        println!("{} now stands for {}", alias, state.canonical_name(alias));
+    } else {
+        let timer_name = state
+            .aliases
+            .get(alias)
+            .ok_or_else(|| AppError::NoSuchAlias(alias.clone()))?;
+        println!("{} -> {}", alias, timer_name);
+    }
+
+    Ok(())
+}
+
+fn adjust(state: &mut AppState, cmd: &AdjustCommand, now: DateTime<Utc>) -> Result<(), AppError> {
+    let timer = state
+        .get_timer_mut(&cmd.timer_name)
+        .ok_or(AppError::NoSuchTimer)?;
+    timer.adjustments.push(Adjustment {
+        time: now,
+        seconds: cmd.amount,
+        comment: cmd.comment.clone().unwrap_or_default(),
+    });
+
+    println!("Total: {}", get_duration_string(&timer.total_duration()));
+
+    Ok(())
+}
+
+fn rename_tag(state: &mut AppState, cmd: &RenameTagCommand) {
+    let renamed = state.rename_tag(&cmd.old, &cmd.new);
+    println!(
+        "Renamed {} to {} in {} record(s)",
+        cmd.old, cmd.new, renamed
+    );
+}
+
+fn remove_tag(state: &mut AppState, cmd: &RemoveTagCommand) -> Result<(), AppError> {
+    let removed = state.remove_tag(&cmd.tag, cmd.timer.as_deref())?;
+    println!("Removed {} from {} record(s)", cmd.tag, removed);
+
+    Ok(())
+}
+
+/// Sums up the duration per tag of the records of the named timers, or of all
+/// timers if none are named. Timers named twice count once.
+fn scoped_tag_totals<'a>(
+    state: &'a AppState,
+    timer_names: &'a [String],
+) -> Result<BTreeMap<&'a str, Duration>, AppError> {
+    let mut names: Vec<&str> = if timer_names.is_empty() {
+        state.timers.keys().map(String::as_str).collect()
+    } else {
+        timer_names
+            .iter()
+            .map(|name| state.canonical_name(name))
+            .collect()
+    };
+    names.sort_unstable();
+    names.dedup();
+
+    let mut timers = vec![];
+    for name in names {
+        timers.push(state.timers.get(name).ok_or(AppError::NoSuchTimer)?);
+    }
+
+    Ok(tag_totals(
+        timers.into_iter().flat_map(|timer| &timer.records),
+    ))
+}
+
+fn tags(state: &AppState, cmd: &TagsCommand) -> Result<(), AppError> {
+    let totals = scoped_tag_totals(state, &cmd.timer)?;
+    if totals.is_empty() {
+        println!("No tagged records");
+    }
+    for (tag, duration) in totals {
+        println!("{:<20} {}", tag, get_duration_string(&duration));
+    }
+
+    Ok(())
+}
+
+/// Writes the state to a file named after `now` in `dir` and returns its path
+fn backup(
+    state: &AppState,
+    dir: &Path,
+    pretty: bool,
+    now: DateTime<Utc>,
+) -> Result<PathBuf, Box<dyn Error>> {
+    create_dir_all(dir)?;
+
+    // backups within the same second are told apart by a counter
+    let stamp = now.format("state-%Y%m%dT%H%M%SZ");
+    let mut path = dir.join(format!("{}.json", stamp));
+    for counter in 1.. {
+        if !path.exists() {
+            break;
+        }
+        path = dir.join(format!("{}-{}.json", stamp, counter));
+    }
+    state.write_to_file(&path, pretty)?;
+    println!(
+        "Backed up {} records to {}",
+        state.record_count(),
+        path.display()
+    );
+
+    Ok(path)
+}
+
+/// Replaces the state with the backup at `--path` after confirming through
+/// `--yes` or `confirm`. The current state is only touched once the backup
+/// has been read successfully.
+fn restore(
+    state: &mut AppState,
+    cmd: &RestoreCommand,
+    confirm: impl FnOnce(&str) -> bool,
+) -> Result<(), Box<dyn Error>> {
+    let backup = AppState::read_backup(&cmd.path)?;
+
+    let prompt = format!(
+        "Replace the current state ({} records) with {} ({} records)?",
+        state.record_count(),
+        cmd.path.display(),
+        backup.record_count()
+    );
+    if !cmd.yes && !confirm(&prompt) {
+        return Err(AppError::NotConfirmed.into());
+    }
+
+    *state = backup;
+    println!("Restored {} records", state.record_count());
+
+    Ok(())
+}
+
+/// Asks a yes/no question, if stdin and stderr are attached to a terminal
+fn confirm_interactively(prompt: &str) -> bool {
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return false;
+    }
+
+    dialoguer::Confirm::new()
+        .with_prompt(prompt)
+        .default(false)
+        .interact_opt()
+        .ok()
+        .flatten()
+        .unwrap_or(false)
+}
+
+/// Resets the active timer, or with `--all` every timer after confirming
+/// through `--yes` or `confirm`. With `--delete-timers` the timers are
+/// removed as well instead of being kept without records.
+fn reset(
+    state: &mut AppState,
+    cmd: &ResetCommand,
+    confirm: impl FnOnce(&str) -> bool,
+) -> Result<(), AppError> {
+    if cmd.all {
+        let prompt = format!(
+            "Remove all {} records of {} timers?",
+            state.record_count(),
+            state.timers.len()
+        );
+        if !cmd.yes && !confirm(&prompt) {
+            return Err(AppError::NotConfirmed);
+        }
+
+        let removed = state.reset_all();
+        if cmd.delete_timers {
+            state.timers.clear();
+            state.aliases.clear();
+            state.active_timer = None;
+        }
+        println!("Removed {} records", removed);
+
+        return Ok(());
+    }
+
+    if cmd.delete_timers {
+        let name = state.active_timer.take().ok_or(AppError::NoSuchTimer)?;
+        state.timers.remove(&name).ok_or(AppError::NoSuchTimer)?;
+        state.aliases.retain(|_, timer_name| *timer_name != name);
+
+        println!("Timer {} deleted", name);
+        return Ok(());
+    }
+
+    let timer = state.get_active_timer_mut().ok_or(AppError::NoSuchTimer)?;
+    timer.records.clear();
+    timer.adjustments.clear();
+
+    println!("Total time reset");
+
+    Ok(())
+}
+
+fn set_rate(state: &mut AppState, cmd: &SetRateCommand) -> Result<(), AppError> {
+    let timer = state
+        .get_timer_mut(&cmd.timer_name)
+        .ok_or(AppError::NoSuchTimer)?;
+    timer.rate = Some(cmd.rate);
+
+    Ok(())
+}
+
+fn edit(state: &mut AppState, cmd: &EditCommand) -> Result<(), AppError> {
+    let timer = state
+        .get_timer_mut(&cmd.timer_name)
+        .ok_or(AppError::NoSuchTimer)?;
+    let record = timer
+        .records
+        .get_mut(cmd.index)
+        .ok_or(AppError::NoSuchRecord)?;
+
+    if let Some(comment) = &cmd.comment {
+        record.comment = comment.clone();
+    }
+    if cmd.rate.is_some() || cmd.clear_rate {
+        record.rate_override = cmd.rate;
+    }
+    record.tags.retain(|tag| !cmd.remove_tag.contains(tag));
+    for tag in &cmd.add_tag {
+        if !record.tags.contains(tag) {
+            record.tags.push(tag.clone());
+        }
+    }
+
+    Ok(())
+}
+
+fn coalesce(state: &mut AppState, cmd: &CoalesceCommand) -> Result<(), AppError> {
+    let timer = state
+        .get_timer_mut(&cmd.timer_name)
+        .ok_or(AppError::NoSuchTimer)?;
+    let merged = timer.merge_adjacent_records(cmd.max_gap);
+
+    println!("Merged {} records, {} left", merged, timer.records_len());
+
+    Ok(())
+}
+
+fn check_record_order(timer_name: &str, timer: &Timer) -> Vec<String> {
+    timer
+        .records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| record.end < record.start)
+        .map(|(i, _)| format!("{}: record {} ends before it starts", timer_name, i))
+        .collect()
+}
+
+fn check_overlaps(timer_name: &str, timer: &Timer) -> Vec<String> {
+    timer
+        .find_overlaps()
+        .into_iter()
+        .map(|(a, b)| format!("{}: records {} and {} overlap", timer_name, a, b))
+        .collect()
+}
+
+/// The overlapping records of all timers, as reported by `check`
+fn overlap_warnings(state: &AppState) -> Vec<String> {
+    state
+        .timer_names_sorted()
+        .into_iter()
+        .flat_map(|name| check_overlaps(name, &state.timers[name]))
+        .collect()
+}
+
+fn check_running_start(timer_name: &str, timer: &Timer, now: DateTime<Utc>) -> Vec<String> {
+    match timer.current_start {
+        Some(start) if start > now => {
+            vec![format!(
+                "{}: running timer was started in the future",
+                timer_name
+            )]
+        }
+        _ => vec![],
+    }
+}
+
+fn check_active_timer(state: &AppState) -> Vec<String> {
+    match &state.active_timer {
+        Some(timer_name) if !state.has_active_timer() => {
+            vec![format!("active timer {} does not exist", timer_name)]
+        }
+        _ => vec![],
+    }
+}
+
+fn check_state(state: &AppState, now: DateTime<Utc>) -> Vec<String> {
+    let mut violations = check_active_timer(state);
+
+    for name in state.timer_names_sorted() {
+        let timer = &state.timers[name];
+
+        violations.extend(check_record_order(name, timer));
+        violations.extend(check_overlaps(name, timer));
+        violations.extend(check_running_start(name, timer, now));
+    }
+
+    violations
+}
+
+fn check(state: &AppState, now: DateTime<Utc>) -> Result<(), AppError> {
+    let violations = check_state(state, now);
+
+    for violation in &violations {
+        println!("{}", violation);
+    }
+
+    if violations.is_empty() {
+        println!("No problems found in {} records", state.record_count());
+        Ok(())
+    } else {
+        Err(AppError::CheckFailed(violations.len()))
+    }
+}
+
+fn import(
+    state: &mut AppState,
+    cmd: &ImportCommand,
+    now: DateTime<Utc>,
+) -> Result<(), Box<dyn Error>> {
+    let text = std::fs::read_to_string(&cmd.path)?;
+    let default_timer = cmd
+        .timer
+        .clone()
+        .or_else(|| state.active_timer_name().map(str::to_owned));
+
+    let count = match cmd.format {
+        ImportFormat::Csv => import_csv(state, &text, default_timer.as_deref(), now)?,
+    };
+
+    println!("Imported {} record(s)", count);
+
+    Ok(())
+}
+
+const DEFAULT_PROMPT_FORMAT: &str = " {name}⏱{elapsed}";
+
+/// Formats a duration as hours and minutes, e.g. `1:20`
+fn get_clock_string(duration: &Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Renders the prompt indicator of the running timer, or an empty string if none is running
+fn prompt_string(state: &AppState, format: &str, now: DateTime<Utc>) -> String {
+    let running = state
+        .active_timer_name()
+        .zip(state.active_record_preview(now));
+
+    match running {
+        Some((name, current)) => format
+            .replace("{name}", name)
+            .replace("{elapsed}", &get_clock_string(&current.duration())),
+        None => String::new(),
+    }
+}
+
+fn prompt(state: &AppState, cmd: &PromptCommand, config: &Config, now: DateTime<Utc>) {
+    let format = cmd
+        .format
+        .as_deref()
+        .or(config.prompt_format.as_deref())
+        .unwrap_or(DEFAULT_PROMPT_FORMAT);
+
+    print!("{}", prompt_string(state, format, now));
+}
+
+/// The name, elapsed time and shortened comment of the active timer if it is
+/// running, or just the elapsed seconds with `--running-duration`
+fn status_string(state: &AppState, cmd: &StatusCommand, now: DateTime<Utc>) -> String {
+    let running = state.active_timer_name().zip(state.get_active_timer());
+    let elapsed = running.and_then(|(_, timer)| timer.current_elapsed(now));
+
+    let mut status = match (running, elapsed) {
+        (_, Some(elapsed)) if cmd.running_duration => return elapsed.as_secs().to_string(),
+        _ if cmd.running_duration => return "0".to_owned(),
+        (Some((name, timer)), Some(elapsed)) => {
+            let mut status = format!("{} running for {}", name, get_duration_string(&elapsed));
+            match timer.current_comment.as_deref() {
+                Some(comment) if !comment.is_empty() => {
+                    status.push_str(": ");
+                    status.push_str(&truncate_ellipsis(comment, cmd.comment_width));
+                }
+                _ => {}
+            }
+            status
+        }
+        _ => "No timer running".to_owned(),
+    };
+    if let Some(timer) = state.get_active_timer().filter(|_| cmd.record_count) {
+        status.push_str(&format!(" ({} record(s))", timer.records_len()));
+    }
+
+    status
+}
+
+/// The records of `timer` `records` lists, with their indices
+fn listed_records<'a>(
+    timer: &'a Timer,
+    cmd: &RecordsCommand,
+    start_of_week: StartOfWeek,
+    now: DateTime<Utc>,
+) -> Result<Vec<(usize, &'a TimerRecord)>, AppError> {
+    let mut filter = RecordFilter::from_options(&cmd.range, start_of_week, now)?;
+    if let Some(keyword) = cmd.range_shortcut() {
+        let (since, until) = resolve_range(keyword, start_of_week, now);
+        filter.since = Some(since);
+        filter.until = Some(until);
+    }
+
+    let mut records: Vec<(usize, &TimerRecord)> = timer
+        .records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| filter.matches(record))
+        .collect();
+    if let Some(limit) = cmd.limit {
+        records.drain(..records.len().saturating_sub(limit));
+    }
+    if cmd.reverse {
+        records.reverse();
+    }
+
+    Ok(records)
+}
+
+fn records(
+    state: &AppState,
+    cmd: &RecordsCommand,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let (_, timer) = state.resolve_timer(cmd.timer_name.as_deref())?;
+    let comment_width = cmd.wrap_comments.or_else(terminal_comment_width);
+
+    for (index, record) in listed_records(timer, cmd, config.start_of_week, now)? {
+        println!(
+            "{}",
+            format_record_line(
+                index,
+                record,
+                Some(now).filter(|_| cmd.relative),
+                comment_width
+            )
+        );
+    }
+
+    Ok(())
+}
+
+fn search(
+    state: &AppState,
+    cmd: &SearchCommand,
+    config: &Config,
+    color: bool,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let predicate = SearchPredicate {
+        matcher: CommentMatcher::new(&cmd.query, cmd.regex, cmd.ignore_case)?,
+        invert: cmd.invert,
+        filter: RecordFilter::from_options(&cmd.range, config.start_of_week, now)?,
+    };
+
+    let highlight = Some(&predicate.matcher).filter(|_| color);
+    for name in search_timers(state, cmd)? {
+        for (index, record) in state.timers[name].records.iter().enumerate() {
+            if predicate.matches(record) {
+                println!("{}", format_search_match(name, index, record, highlight));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The timers `search` looks through, `--timer` or an alias of it standing
+/// for a single one
+fn search_timers<'a>(state: &'a AppState, cmd: &SearchCommand) -> Result<Vec<&'a str>, AppError> {
+    match &cmd.timer {
+        Some(name) => {
+            let (name, _) = state
+                .timers
+                .get_key_value(state.canonical_name(name))
+                .ok_or(AppError::NoSuchTimer)?;
+            Ok(vec![name])
+        }
+        None => Ok(filter_timers(
+            state,
+            &cmd.timers.include,
+            &cmd.timers.exclude,
+        )),
+    }
+}
+
+/// A line of `search` output, with the matches of `highlight` in the comment
+/// highlighted if given
+fn format_search_match(
+    name: &str,
+    index: usize,
+    record: &TimerRecord,
+    highlight: Option<&CommentMatcher>,
+) -> String {
+    let comment = match highlight {
+        Some(matcher) => highlight_ranges(&record.comment, &matcher.find_ranges(&record.comment)),
+        None => record.comment.clone(),
+    };
+
+    let line = format!(
+        "{}  {}{}",
+        name,
+        record_line_prefix(index, record, None),
+        comment
+    );
+    if record.comment.is_empty() {
+        line.trim_end().to_owned()
+    } else {
+        line
+    }
+}
+
+/// The range of `report`, narrowed down to the time after the last marked
+/// report with `--since-last-report`
+fn report_filter(
+    state: &AppState,
+    cmd: &ReportCommand,
+    start_of_week: StartOfWeek,
+    now: DateTime<Utc>,
+) -> Result<RecordFilter, AppError> {
+    let mut filter = RecordFilter::from_options(&cmd.range, start_of_week, now)?;
+    if cmd.since_last_report {
+        filter.since = filter.since.max(state.last_report_at);
+    }
+
+    Ok(filter)
+}
+
+/// The records of `timer` to report on, filtered and billed as set by `cmd`
+fn report_records(
+    timer: &Timer,
+    cmd: &ReportCommand,
+    filter: &RecordFilter,
+    day_boundary: DayBoundary,
+    now: DateTime<Utc>,
+) -> Vec<TimerRecord> {
+    let timer_records = if cmd.include_running {
+        timer.records_with_running(now)
+    } else {
+        timer.records.as_slice().into()
+    };
+    let records: Vec<TimerRecord> = if cmd.clip_range {
+        filter.clipped_records(&timer_records)
+    } else {
+        timer_records
+            .iter()
+            .filter(|record| filter.matches(record))
+            .cloned()
+            .collect()
+    };
+
+    match cmd.daily_minimum {
+        Some(minimum) => {
+            let records: Vec<&TimerRecord> = records.iter().collect();
+            apply_daily_minimum(&records, minimum, cmd.minimum_on, day_boundary)
+        }
+        None => records,
+    }
+}
+
+/// Renders the daily totals of several timers as CSV, with a row per day and
+/// a column per timer, followed by a total column and a total row. Durations
+/// are in seconds, days without time on a timer are 0.
+fn render_day_matrix(columns: &[(&str, Vec<(String, Duration)>)]) -> String {
+    let mut days: Vec<&str> = columns
+        .iter()
+        .flat_map(|(_, groups)| groups.iter().map(|(day, _)| day.as_str()))
+        .collect();
+    days.sort_unstable();
+    days.dedup();
+
+    let mut output = String::from("day");
+    for (name, _) in columns {
+        output.push(',');
+        output.push_str(&csv_field(name));
+    }
+    output.push_str(",total\n");
+
+    let mut column_totals = vec![Duration::ZERO; columns.len()];
+    for day in days {
+        let mut row_total = Duration::ZERO;
+        output.push_str(&csv_field(day));
+        for ((_, groups), column_total) in columns.iter().zip(&mut column_totals) {
+            let duration = groups
+                .iter()
+                .find(|(label, _)| label == day)
+                .map_or(Duration::ZERO, |&(_, duration)| duration);
+            row_total += duration;
+            *column_total += duration;
+            output.push_str(&format!(",{}", duration.as_secs()));
+        }
+        output.push_str(&format!(",{}\n", row_total.as_secs()));
+    }
+
+    output.push_str("total");
+    for column_total in &column_totals {
+        output.push_str(&format!(",{}", column_total.as_secs()));
+    }
+    let total: Duration = column_totals.iter().sum();
+    output.push_str(&format!(",{}\n", total.as_secs()));
+
+    output
+}
+
+fn report(
+    state: &mut AppState,
+    cmd: &ReportCommand,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let filter = report_filter(state, cmd, config.start_of_week, now)?;
+    let day_boundary = cmd.tz_offset.map_or(cmd.day_boundary, DayBoundary::Offset);
+    let round = cmd.round.map(|unit| (unit, cmd.round_scope));
+
+    if cmd.csv_by_day {
+        let columns: Vec<(&str, Vec<(String, Duration)>)> = state
+            .timer_names_sorted()
+            .into_iter()
+            .map(|name| {
+                let timer = &state.timers[name];
+                let records = report_records(timer, cmd, &filter, day_boundary, now);
+                let records: Vec<&TimerRecord> = records.iter().collect();
+                let adjustments: Vec<&Adjustment> = timer.adjustments_in_range(&filter).collect();
+                let (groups, _) = report_totals(
+                    &records,
+                    &adjustments,
+                    GroupBy::Day,
+                    day_boundary,
+                    config.start_of_week,
+                    round,
+                );
+                (name.as_str(), groups)
+            })
+            .filter(|(_, groups)| !groups.is_empty())
+            .collect();
+        print!("{}", render_day_matrix(&columns));
+
+        if cmd.mark {
+            state.last_report_at = Some(now);
+        }
+        return Ok(());
+    }
+
+    let (_, timer) = state.resolve_timer(cmd.timer_name.as_deref())?;
+    let records = report_records(timer, cmd, &filter, day_boundary, now);
+    let records: Vec<&TimerRecord> = records.iter().collect();
+    let adjustments: Vec<&Adjustment> = timer.adjustments_in_range(&filter).collect();
+    let (groups, total) = report_totals(
+        &records,
+        &adjustments,
+        cmd.group_by,
+        day_boundary,
+        config.start_of_week,
+        round,
+    );
+
+    match (cmd.unit, cmd.format) {
+        (Some(unit), _) => println!("{}", format_duration_as(&total, unit)),
+        (None, ReportFormat::Text) => {
+            for (label, duration) in groups {
+                println!("{}  {}", label, get_duration_string(&duration));
+            }
+            println!("Total: {}", get_duration_string(&total));
+        }
+        (None, ReportFormat::Table) => {
+            let counts = grouped_counts(&records, cmd.group_by, day_boundary, config.start_of_week);
+            print!(
+                "{}",
+                render_report_table(cmd.group_by, &groups, &counts, total)
+            );
+        }
+        (None, ReportFormat::Plain) => {
+            for (label, duration) in groups {
+                println!("{}\t{}", label, duration.as_secs());
+            }
+        }
+    }
+
+    if cmd.mark {
+        state.last_report_at = Some(now);
+    }
+
+    Ok(())
+}
+
+fn week(
+    state: &AppState,
+    cmd: &WeekCommand,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let (_, timer) = state.resolve_timer(cmd.timer_name.as_deref())?;
+    let week_start = config
+        .start_of_week
+        .week_start(DayBoundary::Local.day_of(now));
+    let records = if cmd.include_running {
+        timer.records_with_running(now)
+    } else {
+        timer.records.as_slice().into()
+    };
+    let total_of = |week_start: NaiveDate| {
+        let week_end = week_start + chrono::Duration::weeks(1);
+        let (start, end) = (local_midnight(week_start), local_midnight(week_end));
+        adjust_duration(
+            records_duration_in_range(records.iter(), start, end, false),
+            timer.adjustment_seconds(start, end),
+        )
+    };
+    let this_week = total_of(week_start);
+
+    if let Some(unit) = cmd.unit {
+        println!("{}", format_duration_as(&this_week, unit));
+    } else if cmd.compare {
+        let last_week = total_of(week_start - chrono::Duration::weeks(1));
+        print!("{}", render_week_comparison(this_week, last_week));
+    } else {
+        println!(
+            "Week of {}: {}",
+            week_start,
+            get_duration_string(&this_week)
+        );
+    }
+
+    Ok(())
+}
+
+/// Statistics of the durations of a set of records
+#[derive(Debug, PartialEq)]
+struct TimerStats {
+    count: usize,
+    total: Duration,
+    mean: Duration,
+    median: Duration,
+    shortest: Duration,
+    longest: Duration,
+}
+
+impl TimerStats {
+    /// `None` if there are no durations to compute statistics of
+    fn new(mut durations: Vec<Duration>) -> Option<Self> {
+        if durations.is_empty() {
+            return None;
+        }
+
+        durations.sort_unstable();
+        let count = durations.len();
+        let total: Duration = durations.iter().sum();
+        let middle = count / 2;
+        let median = if count.is_multiple_of(2) {
+            (durations[middle - 1] + durations[middle]) / 2
+        } else {
+            durations[middle]
+        };
+
+        Some(Self {
+            count,
+            total,
+            mean: total / count as u32,
+            median,
+            shortest: durations[0],
+            longest: durations[count - 1],
+        })
+    }
+}
+
+/// The durations of the records within `min` and `max`, both inclusive
+fn durations_between(
+    records: &[TimerRecord],
+    min: Option<Duration>,
+    max: Option<Duration>,
+) -> Vec<Duration> {
+    records
+        .iter()
+        .map(TimerRecord::duration)
+        .filter(|&duration| min.is_none_or(|min| duration >= min))
+        .filter(|&duration| max.is_none_or(|max| duration <= max))
+        .collect()
+}
+
+fn render_stats(stats: &TimerStats) -> String {
+    format!(
+        "Records:  {}\nTotal:    {}\nMean:     {}\nMedian:   {}\nShortest: {}\nLongest:  {}\n",
+        stats.count,
+        get_duration_string(&stats.total),
+        get_duration_string(&stats.mean),
+        get_duration_string(&stats.median),
+        get_duration_string(&stats.shortest),
+        get_duration_string(&stats.longest)
+    )
+}
+
+fn stats(state: &AppState, cmd: &StatsCommand) -> Result<(), AppError> {
+    let (_, timer) = state.resolve_timer(cmd.timer_name.as_deref())?;
+
+    match TimerStats::new(durations_between(&timer.records, cmd.min, cmd.max)) {
+        Some(stats) => print!("{}", render_stats(&stats)),
+        None => println!("No records to analyze"),
+    }
+
+    Ok(())
+}
+
+fn render_record_details(timer_name: &str, record: &TimerRecord) -> String {
+    let format_time = |time: DateTime<Utc>| time.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+
+    format!(
+        "Timer:    {}\nStart:    {}\nEnd:      {}\nDuration: {}\nComment:  {}\n",
+        timer_name,
+        format_time(record.start),
+        format_time(record.end),
+        get_duration_string(&record.duration()),
+        record.comment
+    )
+}
+
+fn last(state: &AppState, cmd: &LastCommand) -> Result<(), AppError> {
+    let last = match &cmd.timer_name {
+        Some(name) => {
+            let (name, timer) = state.resolve_timer(Some(name))?;
+            timer
+                .records
+                .iter()
+                .max_by_key(|record| record.end)
+                .map(|record| (name, record))
+        }
+        None => state.last_record(),
+    };
+    let (timer_name, record) = last.ok_or(AppError::NoSuchRecord)?;
+
+    print!("{}", render_record_details(timer_name, record));
+
+    Ok(())
+}
+
+fn first(state: &AppState, cmd: &FirstCommand) -> Result<(), AppError> {
+    let first = match &cmd.timer_name {
+        Some(name) => {
+            let (name, timer) = state.resolve_timer(Some(name))?;
+            timer
+                .records
+                .iter()
+                .min_by_key(|record| record.start)
+                .map(|record| (name, record))
+        }
+        None => state.first_record(),
+    };
+
+    match first {
+        Some((timer_name, record)) => print!("{}", render_record_details(timer_name, record)),
+        None => println!("No records yet"),
+    }
+
+    Ok(())
+}
+
+/// The duration between two user supplied times, see `parse_time`
+fn duration_between(start: &str, end: &str, now: DateTime<Utc>) -> Result<Duration, AppError> {
+    let start = parse_time(start, now)?;
+    let end = parse_time(end, now)?;
+
+    (end - start).to_std().map_err(|_| AppError::EndBeforeStart)
+}
+
+/// `duration` in `unit`, including fractions of it
+fn duration_as(duration: &Duration, unit: DurationUnit) -> f64 {
+    let seconds = duration.as_secs_f64();
+
+    match unit {
+        DurationUnit::Seconds => seconds,
+        DurationUnit::Minutes => seconds / 60.0,
+        DurationUnit::Hours => seconds / 3600.0,
+    }
+}
+
+/// Formats `duration` in `unit` as a bare number with at most two decimals,
+/// like `90` or `1.5`
+fn format_duration_as(duration: &Duration, unit: DurationUnit) -> String {
+    let number = format!("{:.2}", duration_as(duration, unit));
+
+    number
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_owned()
+}
+
+/// Formats `duration` as hours with `decimals` decimals, optionally rounded to
+/// the nearest quarter hour first
+fn format_decimal_hours(duration: &Duration, decimals: usize, quarter: bool) -> String {
+    let mut hours = duration_as(duration, DurationUnit::Hours);
+    if quarter {
+        hours = (hours * 4.0).round() / 4.0;
+    }
+
+    format!("{:.*}", decimals, hours)
+}
+
+fn duration(cmd: &DurationCommand, now: DateTime<Utc>) -> Result<(), AppError> {
+    let duration = duration_between(&cmd.start, &cmd.end, now)?;
+
+    if cmd.decimal_hours {
+        println!(
+            "{}",
+            format_decimal_hours(&duration, cmd.decimal_precision, cmd.quarter)
+        );
+    } else if cmd.hms {
+        let seconds = duration.as_secs();
+        println!("{}:{:02}", get_clock_string(&duration), seconds % 60);
+    } else {
+        println!("{}", get_duration_string(&duration));
+    }
+
+    Ok(())
+}
+
+/// The names of the timers taking part in a command over several timers:
+/// all of them or only the `includes` if given, without the `excludes`
+fn filter_timers<'a>(
+    state: &'a AppState,
+    includes: &[String],
+    excludes: &[String],
+) -> Vec<&'a str> {
+    let listed = |names: &[String], name: &str| {
+        names
+            .iter()
+            .any(|listed| state.canonical_name(listed) == name)
+    };
+
+    state
+        .timer_names_sorted()
+        .into_iter()
+        .map(String::as_str)
+        .filter(|name| includes.is_empty() || listed(includes, name))
+        .filter(|name| !listed(excludes, name))
+        .collect()
+}
+
+fn total(
+    state: &AppState,
+    cmd: &TotalCommand,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let filter = RecordFilter::from_options(&cmd.range, config.start_of_week, now)?;
+    let timer_names = filter_timers(state, &cmd.timers.include, &cmd.timers.exclude);
+    let total = state.grand_total(&timer_names, &filter, cmd.clip_range);
+
+    if let Some(unit) = cmd.unit {
+        println!("{}", format_duration_as(&total, unit));
+        return Ok(());
+    }
+
+    if let Some(group_by) = cmd.group_by {
+        let selected: Vec<TimerRecord> = timer_names
+            .iter()
+            .filter_map(|&name| state.get_timer(name))
+            .flat_map(|timer| {
+                if cmd.clip_range {
+                    filter.clipped_records(&timer.records)
+                } else {
+                    timer.records_in_range(&filter).cloned().collect()
+                }
+            })
+            .collect();
+        let records: Vec<&TimerRecord> = selected.iter().collect();
+        let adjustments: Vec<&Adjustment> =
+            state.adjustments_in_range(&timer_names, &filter).collect();
+        let mut groups =
+            grouped_totals(&records, group_by, DayBoundary::Local, config.start_of_week);
+        add_adjustments(
+            &mut groups,
+            &adjustments,
+            group_by,
+            DayBoundary::Local,
+            config.start_of_week,
+        );
+
+        for (label, duration) in groups {
+            println!("{}  {}", label, get_duration_string(&duration));
+        }
+    }
+    println!("Total: {}", get_duration_string(&total));
+
+    Ok(())
+}
+
+/// Iterates over the records of the named timers that match `filter`
+fn export_records<'a>(
+    state: &'a AppState,
+    timer_names: &'a [&'a str],
+    filter: &'a RecordFilter,
+) -> impl Iterator<Item = ExportRecord<'a>> + 'a {
+    timer_names
+        .iter()
+        .filter_map(move |&name| Some((name, state.get_timer(name)?)))
+        .flat_map(move |(name, timer)| {
+            timer
+                .records_in_range(filter)
+                .map(move |record| ExportRecord {
+                    timer: name,
+                    record,
+                })
+        })
+}
+
+fn write_records<'a, W: Write>(
+    out: &mut W,
+    format: ExportFormat,
+    totals: bool,
+    records: impl IntoIterator<Item = ExportRecord<'a>>,
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        ExportFormat::Csv if totals => write_csv_with_totals(out, records)?,
+        ExportFormat::Csv => write_csv(out, records)?,
+        ExportFormat::Json => write_json(out, records)?,
+        ExportFormat::Jsonl => write_jsonl(out, records)?,
+        ExportFormat::Ndjson => write_ndjson(out, records)?,
+    }
+
+    Ok(())
+}
+
+/// Finds the latest record start in a previous csv or jsonl export
+fn latest_exported_start(
+    contents: &str,
+    format: ExportFormat,
+) -> Result<Option<DateTime<Utc>>, Box<dyn Error>> {
+    #[derive(Deserialize)]
+    struct ExportedRecord {
+        start: DateTime<Utc>,
+    }
+
+    let starts: Vec<DateTime<Utc>> = match format {
+        ExportFormat::Csv => {
+            let rows = parse_csv(contents)?;
+            let header = match rows.first() {
+                Some(header) => header,
+                None => return Ok(None),
+            };
+            let columns = CsvColumns::from_header(header)?;
+
+            rows[1..]
+                .iter()
+                .map(|row| {
+                    let start = row
+                        .get(columns.start)
+                        .map(String::as_str)
+                        .unwrap_or_default();
+                    DateTime::parse_from_rfc3339(start)
+                        .map(|start| start.with_timezone(&Utc))
+                        .map_err(|_| AppError::BadTime(start.to_owned()))
+                })
+                .collect::<Result<_, _>>()?
+        }
+        ExportFormat::Jsonl | ExportFormat::Ndjson => contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str::<ExportedRecord>(line).map(|record| record.start))
+            .collect::<Result<_, _>>()?,
+        ExportFormat::Json => return Err(AppError::CannotAppendJson.into()),
+    };
+
+    Ok(starts.into_iter().max())
+}
+
+/// Appends the records starting after the latest one already in the file at
+/// `path`, writing a csv header only when the file is new. Returns the number
+/// of appended records.
+fn append_export(
+    state: &AppState,
+    timer_names: &[&str],
+    filter: &RecordFilter,
+    format: ExportFormat,
+    path: &Path,
+) -> Result<usize, Box<dyn Error>> {
+    if let ExportFormat::Json = format {
+        return Err(AppError::CannotAppendJson.into());
+    }
+
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let cutoff = latest_exported_start(&contents, format)?;
+    let records: Vec<ExportRecord> = export_records(state, timer_names, filter)
+        .filter(|export| cutoff.is_none_or(|cutoff| export.record.start > cutoff))
+        .collect();
+    let count = records.len();
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    match format {
+        ExportFormat::Csv if contents.trim().is_empty() => write_csv(&mut file, records)?,
+        ExportFormat::Csv => write_csv_rows(&mut file, records)?,
+        ExportFormat::Ndjson => write_ndjson(&mut file, records)?,
+        _ => write_jsonl(&mut file, records)?,
+    }
+
+    Ok(count)
+}
+
+/// Turns a timer name into a file name that is safe on common file systems
+fn sanitize_filename(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' ') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let sanitized = sanitized.trim().trim_start_matches('.');
+
+    if sanitized.is_empty() {
+        "_".to_owned()
+    } else {
+        sanitized.to_owned()
+    }
+}
+
+/// Exports every named timer to `<dir>/<timer>.<ext>`, creating `dir` if
+/// needed. Names that collide after sanitizing get a numbered suffix.
+fn export_to_dir(
+    state: &AppState,
+    timer_names: &[&str],
+    filter: &RecordFilter,
+    format: ExportFormat,
+    totals: bool,
+    dir: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    create_dir_all(dir)?;
+
+    let mut paths: Vec<PathBuf> = vec![];
+    for &name in timer_names {
+        let stem = sanitize_filename(name);
+        let mut path = dir.join(format!("{}.{}", stem, format.extension()));
+
+        let mut suffix = 2;
+        while paths.contains(&path) {
+            path = dir.join(format!("{}-{}.{}", stem, suffix, format.extension()));
+            suffix += 1;
+        }
+        if suffix > 2 {
+            eprintln!(
+                "Warning: file name for timer {} collides with another timer, writing {}",
+                name,
+                path.display()
+            );
+        }
+
+        let mut file = File::create(&path)?;
+        write_records(
+            &mut file,
+            format,
+            totals,
+            export_records(state, &[name], filter),
+        )?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+fn export(
+    state: &AppState,
+    cmd: &ExportCommand,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<(), Box<dyn Error>> {
+    if cmd.totals && !matches!(cmd.format, ExportFormat::Csv) {
+        return Err(AppError::TotalsNeedCsv.into());
+    }
+
+    let mut filter = RecordFilter::from_options(&cmd.range, config.start_of_week, now)?;
+    filter.comment = cmd
+        .comment_grep
+        .as_deref()
+        .map(|pattern| CommentMatcher::new(pattern, true, false))
+        .transpose()?;
+    let timer_names: Vec<&str> = if cmd.all {
+        filter_timers(state, &cmd.timers.include, &cmd.timers.exclude)
+    } else {
+        vec![state.resolve_timer(cmd.timer_name.as_deref())?.0]
+    };
+
+    match (&cmd.output_dir, &cmd.append) {
+        (Some(dir), _) => {
+            let paths = export_to_dir(state, &timer_names, &filter, cmd.format, cmd.totals, dir)?;
+            println!("Exported {} file(s) to {}", paths.len(), dir.display());
+        }
+        (None, Some(path)) => {
+            let count = append_export(state, &timer_names, &filter, cmd.format, path)?;
+            println!("Appended {} record(s) to {}", count, path.display());
+        }
+        (None, None) => {
+            let records = export_records(state, &timer_names, &filter);
+            write_records(
+                &mut std::io::stdout().lock(),
+                cmd.format,
+                cmd.totals,
+                records,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn heatmap(
+    state: &AppState,
+    cmd: &HeatmapCommand,
+    config: &Config,
+    color: bool,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let (_, timer) = state.resolve_timer(cmd.timer_name.as_deref())?;
+    let first_day = match &cmd.month {
+        Some(month) => parse_month(month)?,
+        None => now
+            .with_timezone(&Local)
+            .date()
+            .naive_local()
+            .with_day(1)
+            .unwrap(),
+    };
+
+    let mut totals = daily_totals(timer.records.iter(), DayBoundary::Local);
+    for adjustment in &timer.adjustments {
+        let total = totals
+            .entry(DayBoundary::Local.day_of(adjustment.time))
+            .or_default();
+        *total = adjust_duration(*total, adjustment.seconds);
+    }
+
+    print!(
+        "{}",
+        render_heatmap(&totals, first_day, config.start_of_week, color,)
+    );
+
+    Ok(())
+}
+
+/// Exit code of the `running` command: 0 while a timer runs, 1 otherwise
+fn running_exit_code(state: &AppState) -> i32 {
+    if state.is_running() {
+        0
+    } else {
+        1
+    }
+}
+
+fn invoice(
+    state: &AppState,
+    cmd: &InvoiceCommand,
+    config: &Config,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let first_day = match &cmd.month {
+        Some(month) => parse_month(month)?,
+        None => now
+            .with_timezone(&Local)
+            .date()
+            .naive_local()
+            .with_day(1)
+            .unwrap(),
+    };
+    let timer_names: Vec<&str> = if cmd.all {
+        filter_timers(state, &cmd.timers.include, &cmd.timers.exclude)
+    } else {
+        vec![state.resolve_timer(cmd.timer_name.as_deref())?.0]
+    };
+    let currency = cmd
+        .currency
+        .as_deref()
+        .or(config.currency.as_deref())
+        .unwrap_or("EUR");
+
+    let invoice = compute_invoice(state, &timer_names, first_day, cmd.round_currency);
+    print!(
+        "{}",
+        render_invoice(&invoice, currency, cmd.format, cmd.locale.or(config.locale))
+    );
+
+    Ok(())
+}
+
+fn document_schema(document: SchemaDocument) -> schemars::schema::RootSchema {
+    match document {
+        SchemaDocument::State => schemars::schema_for!(AppState),
+        SchemaDocument::Export => schemars::schema_for!(ExportRecord),
+        SchemaDocument::NdjsonExport => schemars::schema_for!(NdjsonRecord),
+    }
+}
+
+fn schema(cmd: &SchemaCommand) -> Result<(), serde_json::Error> {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&document_schema(cmd.document))?
+    );
+
+    Ok(())
+}
+
+/// Renders the `env` block as `key: value` lines, settings keyed as `config.<name>`
+fn render_env(info: &EnvInfo) -> Result<String, serde_json::Error> {
+    fn value_string(value: &serde_json::Value) -> String {
+        match value {
+            serde_json::Value::Null => "(unset)".to_owned(),
+            serde_json::Value::String(string) => string.clone(),
+            value => value.to_string(),
+        }
+    }
+
+    let mut lines = vec![];
+    if let serde_json::Value::Object(fields) = serde_json::to_value(info)? {
+        for (key, value) in fields {
+            match value {
+                serde_json::Value::Object(settings) => {
+                    for (name, value) in settings {
+                        lines.push((format!("{}.{}", key, name), value_string(&value)));
+                    }
+                }
+                value => lines.push((key, value_string(&value))),
+            }
+        }
+    }
+
+    let mut output = String::new();
+    for (key, value) in lines {
+        output += &format!("{:<25} {}\n", key + ":", value);
+    }
+
+    Ok(output)
+}
+
+fn env(cmd: &EnvCommand, info: &EnvInfo) -> Result<(), Box<dyn Error>> {
+    if cmd.json || cmd.json_pretty {
+        println!("{}", json_string(info, cmd.json_pretty)?);
+    } else {
+        print!("{}", render_env(info)?);
+    }
+
+    Ok(())
+}
+
+/// Runs the command and returns the exit code of the process
+fn run(opts: Opts) -> Result<i32, Box<dyn Error>> {
+    let config_path = get_configfile_path()?;
+    let config = Config::read_from_file(&config_path)?;
+    let state_path = get_statefile_path()?;
+    let now = Utc::now();
+    let color = should_colorize(
+        if opts.no_color {
+            ColorChoice::Never
+        } else {
+            opts.color
+        },
+        |name| std::env::var(name).ok(),
+        std::io::stdout().is_terminal(),
+    );
+
+    execute(&opts, &config, color, &state_path, &config_path, now)
+}
+
+/// Runs the command on the state at `state_path`, saving it afterwards unless
+/// the command only reads it. Returns the exit code of the process, which is
+/// only used once the state is unlocked again.
+fn execute(
+    opts: &Opts,
+    config: &Config,
+    color: bool,
+    state_path: &Path,
+    config_path: &Path,
+    now: DateTime<Utc>,
+) -> Result<i32, Box<dyn Error>> {
+    let auto_checkpoint = config
+        .auto_checkpoint
+        .as_deref()
+        .map(|interval| {
+            humantime::parse_duration(interval)
+                .map_err(|_| AppError::InvalidDuration(interval.to_owned()))
+        })
+        .transpose()?;
+
+    let write = !opts.subcmd.is_read_only();
+    with_state(
+        state_path,
+        config.strict_state,
+        write,
+        opts.pretty_state || config.pretty_state,
+        LOCK_ATTEMPTS,
+        |state| {
+            // only commands saving the state anyway keep the checkpoints
+            // deliberately skipped for read-only commands, see `Config::auto_checkpoint`
+            if let Some(interval) = auto_checkpoint.filter(|_| write) {
+                state.maybe_checkpoint(now, interval);
+            }
+            if opts.warn_overlap || config.warn_on_overlap {
+                for warning in overlap_warnings(state) {
+                    eprintln!("mtt: warning: {}", warning);
+                }
+            }
+
+            run_command(opts, state, config, color, state_path, config_path, now)
+        },
+    )
+}
+
+fn run_command(
+    opts: &Opts,
+    state: &mut AppState,
+    config: &Config,
+    color: bool,
+    state_path: &Path,
+    config_path: &Path,
+    now: DateTime<Utc>,
+) -> Result<i32, Box<dyn Error>> {
+    match &opts.subcmd {
+        SubCommand::Start(cmd) => start(state, cmd, config, now)?,
+        SubCommand::Stop(cmd) => stop(state, cmd, config, now)?,
+        SubCommand::Abort => abort(state)?,
+        SubCommand::Show(cmd) => show(state, cmd, config, now)?,
+        SubCommand::List(cmd) => list(state, cmd, color, now)?,
+        SubCommand::Describe(cmd) => describe(state, cmd)?,
+        SubCommand::Reset(cmd) => reset(state, cmd, confirm_interactively)?,
+        SubCommand::SetRate(cmd) => set_rate(state, cmd)?,
+        SubCommand::Edit(cmd) => edit(state, cmd)?,
+        SubCommand::Check => check(state, now)?,
+        SubCommand::Import(cmd) => import(state, cmd, now)?,
+        SubCommand::Prompt(cmd) => prompt(state, cmd, config, now),
+        SubCommand::Records(cmd) => records(state, cmd, config, now)?,
+        SubCommand::Report(cmd) => report(state, cmd, config, now)?,
+        SubCommand::Export(cmd) => export(state, cmd, config, now)?,
+        SubCommand::Heatmap(cmd) => heatmap(state, cmd, config, color, now)?,
+        SubCommand::Search(cmd) => search(state, cmd, config, color, now)?,
+        SubCommand::Running => return Ok(running_exit_code(state)),
+        SubCommand::Status(cmd) => println!("{}", status_string(state, cmd, now)),
+        SubCommand::Invoice(cmd) => invoice(state, cmd, config, now)?,
+        SubCommand::Total(cmd) => total(state, cmd, config, now)?,
+        SubCommand::Coalesce(cmd) => coalesce(state, cmd)?,
+        SubCommand::Week(cmd) => week(state, cmd, config, now)?,
+        SubCommand::Split(cmd) => split(state, cmd, now)?,
+        SubCommand::SetColor(cmd) => set_color(state, cmd)?,
+        SubCommand::SetGoal(cmd) => set_goal(state, cmd)?,
+        SubCommand::Pin(cmd) => pin(state, cmd, true)?,
+        SubCommand::Unpin(cmd) => pin(state, cmd, false)?,
+        SubCommand::Alias(cmd) => alias(state, cmd)?,
+        SubCommand::Backup(cmd) => {
+            let default_dir = state_path.with_file_name("backups");
+            backup(
+                state,
+                cmd.dir.as_deref().unwrap_or(&default_dir),
+                opts.pretty_state || config.pretty_state,
+                now,
+            )?;
+        }
+        SubCommand::Restore(cmd) => restore(state, cmd, confirm_interactively)?,
+        SubCommand::Adjust(cmd) => adjust(state, cmd, now)?,
+        SubCommand::RenameTag(cmd) => rename_tag(state, cmd),
+        SubCommand::RemoveTag(cmd) => remove_tag(state, cmd)?,
+        SubCommand::Tags(cmd) => tags(state, cmd)?,
+        SubCommand::Last(cmd) => last(state, cmd)?,
+        SubCommand::First(cmd) => first(state, cmd)?,
+        SubCommand::Stats(cmd) => stats(state, cmd)?,
+        SubCommand::Duration(cmd) => duration(cmd, now)?,
+        SubCommand::Schema(cmd) => schema(cmd)?,
+        SubCommand::Env(cmd) => env(
+            cmd,
+            &EnvInfo::new(
+                state_path,
+                config_path,
+                config,
+                state.record_count(),
+                color,
+                now,
+            ),
+        )?,
+    };
+
+    Ok(0)
+}
+
+fn main() {
+    let opts = Opts::parse();
+
+    match run(opts) {
+        Ok(0) => {}
+        Ok(code) => std::process::exit(code),
+        Err(err) => {
+            eprintln!("mtt: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timerrecord_duration() {
+        let duration = Duration::from_secs(1234);
+        let start = Utc::now();
+        let end = start + chrono::Duration::from_std(duration).unwrap();
+
+        let record = TimerRecord::new(start, end, "".to_owned());
+
+        assert_eq!(record.duration(), duration);
+
+        // zero duration
+        let record = TimerRecord::new(start, start, "".to_owned());
+
+        assert_eq!(record.duration(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_timer_total_duration() {
+        let duration = Duration::from_secs(1234);
+        let start = Utc::now();
+        let end = start + chrono::Duration::from_std(duration).unwrap();
+        let record = TimerRecord::new(start, end, "".to_owned());
 
         let duration2 = Duration::from_secs(321);
         let start2 = Utc::now();
         let end2 = start2 + chrono::Duration::from_std(duration2).unwrap();
         let record2 = TimerRecord::new(start2, end2, "Playing solitaire".to_owned());
 
-        let total_duration = duration + duration2;
+        let total_duration = duration + duration2;
+
+        let timer = Timer {
+            records: vec![record, record2],
+            current_start: None,
+            ..Timer::default()
+        };
+
+        assert_eq!(timer.total_duration(), total_duration);
+    }
+
+    #[test]
+    fn test_adjust() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(18, 0, 0);
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.get_timer_mut("work").unwrap().records = vec![record_at(9, 10), record_at(11, 12)];
+
+        let cmd = AdjustCommand {
+            timer_name: "work".to_owned(),
+            amount: parse_signed_duration("-30m").unwrap(),
+            comment: Some("Counted the lunch break".to_owned()),
+        };
+        adjust(&mut state, &cmd, now).unwrap();
+
+        let timer = state.get_timer("work").unwrap();
+        assert_eq!(timer.total_duration(), Duration::from_secs(90 * 60));
+        assert_eq!(timer.records_len(), 2);
+
+        // the total doesn't go below zero
+        let cmd = AdjustCommand {
+            amount: parse_signed_duration("-5h").unwrap(),
+            ..cmd
+        };
+        adjust(&mut state, &cmd, now).unwrap();
+        assert_eq!(
+            state.get_timer("work").unwrap().total_duration(),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_adjustments_in_totals() {
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+        let adjustment = |time: DateTime<Utc>, seconds: i64| Adjustment {
+            time,
+            seconds,
+            comment: String::new(),
+        };
+        let mut state = AppState::default();
+        state.create_timer("work");
+        let timer = state.get_timer_mut("work").unwrap();
+        timer.records = vec![record_at(9, 10), record_at(11, 12)];
+        timer.adjustments = vec![
+            adjustment(Utc.ymd(2022, 4, 1).and_hms(18, 0, 0), -30 * 60),
+            adjustment(Utc.ymd(2022, 3, 20).and_hms(12, 0, 0), 60 * 60),
+        ];
+        let timer = state.get_timer("work").unwrap();
+
+        let everything = RecordFilter::default();
+        let adjustments: Vec<&Adjustment> = timer.adjustments_in_range(&everything).collect();
+        let records: Vec<&TimerRecord> = timer.records.iter().collect();
+        let (groups, total) = report_totals(
+            &records,
+            &adjustments,
+            GroupBy::Day,
+            DayBoundary::Utc,
+            StartOfWeek::Monday,
+            None,
+        );
+        assert_eq!(timer.total_duration(), minutes(150));
+        assert_eq!(
+            state.grand_total(&["work"], &everything, false),
+            minutes(150)
+        );
+        assert_eq!(total, minutes(150));
+        assert_eq!(
+            groups,
+            vec![
+                ("2022-03-20".to_owned(), minutes(60)),
+                ("2022-04-01".to_owned(), minutes(90)),
+            ]
+        );
+
+        // only the adjustment made on the day counts towards it
+        let (start, end) = (
+            Utc.ymd(2022, 4, 1).and_hms(0, 0, 0),
+            Utc.ymd(2022, 4, 2).and_hms(0, 0, 0),
+        );
+        let day = RecordFilter {
+            since: Some(start),
+            until: Some(end),
+            ..RecordFilter::default()
+        };
+        let adjustments: Vec<&Adjustment> = timer.adjustments_in_range(&day).collect();
+        let (_, total) = report_totals(
+            &records,
+            &adjustments,
+            GroupBy::Week,
+            DayBoundary::Utc,
+            StartOfWeek::Monday,
+            None,
+        );
+        assert_eq!(state.grand_total(&["work"], &day, false), minutes(90));
+        assert_eq!(timer.duration_in_range(start, end, false), minutes(90));
+        assert_eq!(total, minutes(90));
+
+        // grouping by tag leaves the untagged adjustments out
+        let mut groups = vec![("billable".to_owned(), minutes(30))];
+        add_adjustments(
+            &mut groups,
+            &adjustments,
+            GroupBy::Tag,
+            DayBoundary::Utc,
+            StartOfWeek::Monday,
+        );
+        assert_eq!(groups, vec![("billable".to_owned(), minutes(30))]);
+    }
+
+    #[test]
+    fn test_parse_signed_duration() {
+        assert_eq!(parse_signed_duration("-30m"), Ok(-1800));
+        assert_eq!(parse_signed_duration("+1h 30m"), Ok(5400));
+        assert_eq!(parse_signed_duration("45s"), Ok(45));
+        assert_eq!(
+            parse_signed_duration("-soon"),
+            Err(AppError::InvalidDuration("-soon".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("80"), Ok(80.0));
+        assert_eq!(parse_rate("0"), Ok(0.0));
+        for input in ["-5", "NaN", "inf", "lots"] {
+            assert_eq!(
+                parse_rate(input),
+                Err(AppError::InvalidRate(input.to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_timer_duration_in_range() {
+        // 8-9 lies before the range, 9:30-10:30 straddles its start, 11-12 is
+        // inside and 13-15 straddles its end
+        let straddling_start = TimerRecord::new(
+            Utc.ymd(2022, 4, 1).and_hms(9, 30, 0),
+            Utc.ymd(2022, 4, 1).and_hms(10, 30, 0),
+            "".to_owned(),
+        );
+        let timer = Timer {
+            records: vec![
+                record_at(8, 9),
+                straddling_start,
+                record_at(11, 12),
+                record_at(13, 15),
+            ],
+            ..Timer::default()
+        };
+        let start = Utc.ymd(2022, 4, 1).and_hms(10, 0, 0);
+        let end = Utc.ymd(2022, 4, 1).and_hms(14, 0, 0);
+
+        // whole records by their start
+        assert_eq!(timer.duration_in_range(start, end, false), hours(3));
+        // only the parts within the range
+        assert_eq!(
+            timer.duration_in_range(start, end, true),
+            Duration::from_secs(30 * 60) + hours(2)
+        );
+        assert_eq!(timer.duration_in_range(end, end, true), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_timer_cost_with_rate_overrides() {
+        let start = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+
+        let regular = TimerRecord::new(start, start + chrono::Duration::hours(2), "".to_owned());
+        let mut rush =
+            TimerRecord::new(start, start + chrono::Duration::minutes(30), "".to_owned());
+        rush.rate_override = Some(150.0);
+
+        let mut timer = Timer {
+            records: vec![regular, rush],
+            rate: Some(60.0),
+            ..Timer::default()
+        };
+
+        // 2h at the timer's rate plus 30min at the override
+        assert_eq!(
+            timer.cost(CurrencyRounding::TwoDecimals),
+            2.0 * 60.0 + 0.5 * 150.0
+        );
+
+        // without a timer rate only the override is billed
+        timer.rate = None;
+        assert_eq!(timer.cost(CurrencyRounding::TwoDecimals), 0.5 * 150.0);
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(1234.5, None), "1234.50");
+        assert_eq!(format_amount(1234.5, Some(NumberLocale::En)), "1,234.50");
+        assert_eq!(format_amount(1234.5, Some(NumberLocale::De)), "1.234,50");
+        assert_eq!(
+            format_amount(-1234567.891, Some(NumberLocale::De)),
+            "-1.234.567,89"
+        );
+        assert_eq!(format_amount(999.999, Some(NumberLocale::En)), "1,000.00");
+        assert_eq!(format_amount(12.0, Some(NumberLocale::De)), "12,00");
+    }
+
+    #[test]
+    fn test_currency_rounding() {
+        assert_eq!(CurrencyRounding::TwoDecimals.apply(10.005), 10.01);
+        assert_eq!(CurrencyRounding::TwoDecimals.apply(10.004), 10.0);
+        assert_eq!(CurrencyRounding::Nearest.apply(10.005), 10.0);
+        assert_eq!(CurrencyRounding::Nearest.apply(10.5), 11.0);
+        assert_eq!(CurrencyRounding::Ceil.apply(10.005), 10.01);
+        assert_eq!(CurrencyRounding::Ceil.apply(10.001), 10.01);
+        assert_eq!(CurrencyRounding::Ceil.apply(10.0), 10.0);
+    }
+
+    #[test]
+    fn test_timer_cost_rounding() {
+        let start = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+        // a minute at 600.3 per hour costs 10.005
+        let timer = Timer {
+            records: vec![TimerRecord::new(
+                start,
+                start + chrono::Duration::minutes(1),
+                "".to_owned(),
+            )],
+            rate: Some(600.3),
+            ..Timer::default()
+        };
+
+        assert_eq!(timer.cost(CurrencyRounding::TwoDecimals), 10.01);
+        assert_eq!(timer.cost(CurrencyRounding::Nearest), 10.0);
+        assert_eq!(timer.cost(CurrencyRounding::Ceil), 10.01);
+    }
+
+    #[test]
+    fn test_edit_rate_override() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        let start = Utc::now();
+        state
+            .get_timer_mut("work")
+            .unwrap()
+            .records
+            .push(TimerRecord::new(start, start, "".to_owned()));
+
+        let mut cmd = EditCommand {
+            timer_name: "work".to_owned(),
+            index: 0,
+            comment: None,
+            rate: Some(90.0),
+            clear_rate: false,
+            add_tag: vec![],
+            remove_tag: vec![],
+        };
+        edit(&mut state, &cmd).unwrap();
+        assert_eq!(
+            state.get_timer("work").unwrap().records[0].rate_override,
+            Some(90.0)
+        );
+
+        cmd.rate = None;
+        cmd.clear_rate = true;
+        edit(&mut state, &cmd).unwrap();
+        assert_eq!(
+            state.get_timer("work").unwrap().records[0].rate_override,
+            None
+        );
+
+        cmd.index = 1;
+        assert_eq!(edit(&mut state, &cmd).unwrap_err(), AppError::NoSuchRecord);
+    }
+
+    #[test]
+    fn test_edit_tags() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        let mut first = record_at(9, 10);
+        first.comment = "Planning".to_owned();
+        first.tags = vec!["meeting".to_owned()];
+        state.get_timer_mut("work").unwrap().records = vec![first, record_at(10, 11)];
+
+        let mut cmd = EditCommand {
+            timer_name: "work".to_owned(),
+            index: 0,
+            comment: None,
+            rate: None,
+            clear_rate: false,
+            add_tag: vec!["dev".to_owned(), "meeting".to_owned()],
+            remove_tag: vec![],
+        };
+        edit(&mut state, &cmd).unwrap();
+
+        cmd.add_tag = vec![];
+        cmd.remove_tag = vec!["meeting".to_owned(), "unknown".to_owned()];
+        edit(&mut state, &cmd).unwrap();
+
+        let records = &state.get_timer("work").unwrap().records;
+        assert_eq!(records[0].tags, ["dev"]);
+        assert_eq!(records[0].comment, "Planning");
+        assert!(records[1].tags.is_empty());
+
+        cmd.index = 2;
+        assert_eq!(edit(&mut state, &cmd).unwrap_err(), AppError::NoSuchRecord);
+    }
+
+    #[test]
+    fn test_active_timer_name() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        assert_eq!(state.active_timer_name(), None);
+
+        state.set_timer_active("work").unwrap();
+        assert_eq!(state.active_timer_name(), Some("work"));
+
+        state.timers.remove("work");
+        assert_eq!(state.active_timer_name(), None);
+        assert_eq!(state.active_timer.as_deref(), Some("work"));
+
+        // loading clears the dangling name for good
+        state.validate_active_timer(false).unwrap();
+        assert_eq!(state.active_timer, None);
+    }
+
+    #[test]
+    fn test_appstate_set_active_timer_nonexisting() {
+        let mut state = AppState::default();
+
+        assert_eq!(
+            state.set_timer_active("something").unwrap_err(),
+            AppError::NoSuchTimer
+        );
+    }
+
+    #[test]
+    fn test_appstate_create_timer() {
+        let mut state = AppState::default();
+        let timer_name = "timer name";
+
+        state.create_timer(timer_name).unwrap();
+
+        // can't create a second timer with the same name
+        assert!(state.create_timer(timer_name).is_none());
+    }
+
+    #[test]
+    fn test_appstate_get_timer() {
+        let mut state = AppState::default();
+        let timer_name = "timer name";
+
+        state.create_timer(timer_name).unwrap();
+
+        let timer1 = state.get_timer(timer_name).unwrap();
+
+        let timer2 = state.get_timer(timer_name).unwrap();
+
+        assert!(std::ptr::eq(timer1, timer2));
+    }
+
+    fn start_command(timer_name: &str, create: bool, no_create: bool) -> StartCommand {
+        StartCommand {
+            timer_name: Some(timer_name.to_owned()),
+            create,
+            no_create,
+            comment: None,
+            force: false,
+        }
+    }
+
+    #[test]
+    fn test_start_create() {
+        let mut state = AppState::default();
+        let cmd = start_command("work", true, false);
+
+        start(&mut state, &cmd, &Config::default(), Utc::now()).unwrap();
+
+        assert!(state.get_timer("work").unwrap().is_running());
+    }
+
+    #[test]
+    fn test_start_alias() {
+        let mut state = AppState::default();
+        state.create_timer("myproject");
+        state.create_timer("other");
+        state.set_alias("p", "myproject").unwrap();
+
+        start(
+            &mut state,
+            &start_command("p", true, false),
+            &Config::default(),
+            Utc::now(),
+        )
+        .unwrap();
+
+        assert_eq!(state.active_timer.as_deref(), Some("myproject"));
+        assert!(state.get_timer("myproject").unwrap().is_running());
+        assert!(!state.timers.contains_key("p"));
+    }
+
+    #[test]
+    fn test_set_alias_errors() {
+        let mut state = AppState::default();
+        state.create_timer("myproject");
+        state.create_timer("p");
+
+        assert_eq!(
+            state.set_alias("p", "myproject").unwrap_err(),
+            AppError::AliasCollision("p".to_owned())
+        );
+        assert_eq!(
+            state.set_alias("x", "missing").unwrap_err(),
+            AppError::NoSuchTimer
+        );
+
+        // aliases of aliases point at the timer directly
+        state.set_alias("m", "myproject").unwrap();
+        state.set_alias("mm", "m").unwrap();
+        assert_eq!(state.aliases["mm"], "myproject");
+    }
+
+    #[test]
+    fn test_alias_read_only() {
+        let read_only = |args: &[&str]| {
+            let opts = Opts::try_parse_from(["mtt", "alias"].iter().chain(args)).unwrap();
+            opts.subcmd.is_read_only()
+        };
+
+        assert!(read_only(&[]));
+        assert!(read_only(&["p"]));
+        assert!(!read_only(&["p", "myproject"]));
+        assert!(!read_only(&["p", "--remove"]));
+    }
+
+    #[test]
+    fn test_start_no_create_overrides_create() {
+        let mut state = AppState::default();
+        let cmd = start_command("wrok", true, true);
+
+        assert_eq!(
+            start(&mut state, &cmd, &Config::default(), Utc::now()).unwrap_err(),
+            AppError::NoSuchTimer
+        );
+        assert!(state.get_timer("wrok").is_none());
+    }
+
+    #[test]
+    fn test_start_no_create_config() {
+        let mut state = AppState::default();
+        let config = Config {
+            no_create: true,
+            ..Config::default()
+        };
+
+        let cmd = start_command("wrok", true, false);
+        assert_eq!(
+            start(&mut state, &cmd, &config, Utc::now()).unwrap_err(),
+            AppError::NoSuchTimer
+        );
+        assert!(state.get_timer("wrok").is_none());
+
+        // existing timers still start
+        state.create_timer("work");
+        let cmd = start_command("work", false, false);
+        start(&mut state, &cmd, &config, Utc::now()).unwrap();
+        assert!(state.get_timer("work").unwrap().is_running());
+    }
+
+    fn record_at(start_hour: u32, end_hour: u32) -> TimerRecord {
+        TimerRecord::new(
+            Utc.ymd(2022, 4, 1).and_hms(start_hour, 0, 0),
+            Utc.ymd(2022, 4, 1).and_hms(end_hour, 0, 0),
+            "".to_owned(),
+        )
+    }
+
+    #[test]
+    fn test_check_record_order() {
+        let timer = Timer {
+            records: vec![record_at(9, 10), record_at(12, 11)],
+            ..Timer::default()
+        };
+
+        assert_eq!(
+            check_record_order("work", &timer),
+            vec!["work: record 1 ends before it starts"]
+        );
+    }
+
+    #[test]
+    fn test_check_overlaps() {
+        let timer = Timer {
+            records: vec![record_at(13, 14), record_at(9, 12), record_at(11, 13)],
+            ..Timer::default()
+        };
+
+        // back to back records don't overlap
+        assert_eq!(timer.find_overlaps(), vec![(1, 2)]);
+        assert_eq!(
+            check_overlaps("work", &timer),
+            vec!["work: records 1 and 2 overlap"]
+        );
+    }
+
+    #[test]
+    fn test_overlap_warnings() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.get_timer_mut("work").unwrap().records = vec![record_at(9, 10), record_at(10, 12)];
+        state.get_timer_mut("hobby").unwrap().records = vec![record_at(9, 11)];
+
+        // records of different timers may overlap
+        assert!(overlap_warnings(&state).is_empty());
+
+        state
+            .get_timer_mut("hobby")
+            .unwrap()
+            .records
+            .push(record_at(10, 11));
+        assert_eq!(overlap_warnings(&state), ["hobby: records 0 and 1 overlap"]);
+    }
+
+    #[test]
+    fn test_check_running_start() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut timer = Timer::default();
+
+        timer.start_timer(now - chrono::Duration::hours(1)).unwrap();
+        assert!(check_running_start("work", &timer, now).is_empty());
+
+        timer.current_start = None;
+        timer.start_timer(now + chrono::Duration::hours(1)).unwrap();
+        assert_eq!(check_running_start("work", &timer, now).len(), 1);
+    }
+
+    #[test]
+    fn test_check_active_timer() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.set_timer_active("work").unwrap();
+        assert!(check_active_timer(&state).is_empty());
+
+        state.timers.remove("work");
+        assert_eq!(
+            check_active_timer(&state),
+            vec!["active timer work does not exist"]
+        );
+        assert_eq!(
+            check(&state, Utc::now()).unwrap_err(),
+            AppError::CheckFailed(1)
+        );
+    }
+
+    #[test]
+    fn test_parse_csv_quoting() {
+        let rows = parse_csv("a,\"b, \"\"quoted\"\"\"\r\n\n\"multi\nline\",c\n").unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                vec!["a".to_owned(), "b, \"quoted\"".to_owned()],
+                vec!["multi\nline".to_owned(), "c".to_owned()],
+            ]
+        );
+        assert!(parse_csv("\"open").is_err());
+    }
+
+    #[test]
+    fn test_import_csv_shuffled_columns() {
+        let mut state = AppState::default();
+        let csv = "Comment,Billable,END,timer,Start,Tags\n\
+                   \"Fixing bugs, mostly\",yes,2022-04-01T10:00:00Z,work,2022-04-01T09:00:00Z,dev;bugs\n\
+                   Lunch,no,2022-04-01T12:30:00Z,private,2022-04-01T12:00:00Z,\n";
+
+        assert_eq!(import_csv(&mut state, csv, None, Utc::now()).unwrap(), 2);
+
+        let work = state.get_timer("work").unwrap();
+        assert_eq!(work.records.len(), 1);
+        assert_eq!(work.records[0].comment, "Fixing bugs, mostly");
+        assert_eq!(work.records[0].tags, vec!["dev", "bugs"]);
+        assert_eq!(work.records[0].duration(), Duration::from_secs(3600));
+
+        let private = state.get_timer("private").unwrap();
+        assert_eq!(private.records[0].duration(), Duration::from_secs(1800));
+        assert!(private.records[0].tags.is_empty());
+    }
+
+    #[test]
+    fn test_import_csv_default_timer() {
+        let mut state = AppState::default();
+        let csv = "end,start\n2022-04-01T10:00:00Z,2022-04-01T09:00:00Z\n";
+
+        assert_eq!(
+            import_csv(&mut state, csv, None, Utc::now()).unwrap_err(),
+            AppError::NoSuchTimer
+        );
+        assert_eq!(
+            import_csv(&mut state, csv, Some("work"), Utc::now()).unwrap(),
+            1
+        );
+        assert_eq!(state.get_timer("work").unwrap().records[0].comment, "");
+    }
+
+    #[test]
+    fn test_import_csv_missing_column() {
+        let mut state = AppState::default();
+        let csv = "start,comment\n2022-04-01T09:00:00Z,Meeting\n";
+
+        assert_eq!(
+            import_csv(&mut state, csv, Some("work"), Utc::now()).unwrap_err(),
+            AppError::MissingCsvColumn("end")
+        );
+        assert!(state.get_timer("work").is_none());
+    }
+
+    #[test]
+    fn test_prompt_string() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        state.create_timer("proj");
+        state.set_timer_active("proj").unwrap();
+
+        assert_eq!(prompt_string(&state, DEFAULT_PROMPT_FORMAT, now), "");
+
+        state
+            .get_active_timer_mut()
+            .unwrap()
+            .start_timer(now - chrono::Duration::minutes(80))
+            .unwrap();
+
+        assert_eq!(
+            prompt_string(&state, DEFAULT_PROMPT_FORMAT, now),
+            " proj⏱1:20"
+        );
+        assert_eq!(
+            prompt_string(&state, "[{elapsed} on {name}]", now),
+            "[1:20 on proj]"
+        );
+    }
+
+    #[test]
+    fn test_status_string() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        state.create_timer("proj");
+        state.set_timer_active("proj").unwrap();
+        let mut cmd = StatusCommand {
+            running_duration: false,
+            comment_width: 12,
+            record_count: false,
+        };
+
+        assert_eq!(status_string(&state, &cmd, now), "No timer running");
+        cmd.running_duration = true;
+        assert_eq!(status_string(&state, &cmd, now), "0");
+
+        state
+            .get_active_timer_mut()
+            .unwrap()
+            .start_timer(now - chrono::Duration::minutes(80))
+            .unwrap();
+
+        assert_eq!(status_string(&state, &cmd, now), "4800");
+        cmd.running_duration = false;
+        assert_eq!(status_string(&state, &cmd, now), "proj running for 1h 20m");
+
+        state.get_active_timer_mut().unwrap().current_comment =
+            Some("Reviewing the export formats".to_owned());
+        assert_eq!(
+            status_string(&state, &cmd, now),
+            "proj running for 1h 20m: Reviewing t…"
+        );
+
+        let earlier = now - chrono::Duration::hours(3);
+        state
+            .get_active_timer_mut()
+            .unwrap()
+            .records
+            .push(TimerRecord::new(
+                earlier,
+                earlier + chrono::Duration::minutes(30),
+                "".to_owned(),
+            ));
+        cmd.record_count = true;
+        assert_eq!(
+            status_string(&state, &cmd, now),
+            "proj running for 1h 20m: Reviewing t… (1 record(s))"
+        );
+    }
+
+    #[test]
+    fn test_truncate_ellipsis() {
+        assert_eq!(truncate_ellipsis("Planning", 8), "Planning");
+        assert_eq!(truncate_ellipsis("Planning", 5), "Plan…");
+        assert_eq!(truncate_ellipsis("Planning", 1), "…");
+        assert_eq!(truncate_ellipsis("Planning", 0), "");
+        // counts characters, not bytes
+        assert_eq!(truncate_ellipsis("Überprüfung", 11), "Überprüfung");
+        assert_eq!(truncate_ellipsis("Überprüfung", 7), "Überpr…");
+        assert_eq!(truncate_ellipsis("日本語のメモ", 4), "日本語…");
+    }
+
+    #[test]
+    fn test_records_in_range_min_duration() {
+        let start = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+        let accidental =
+            TimerRecord::new(start, start + chrono::Duration::seconds(2), "".to_owned());
+        let real = TimerRecord::new(start, start + chrono::Duration::minutes(5), "".to_owned());
+        let timer = Timer {
+            records: vec![accidental, real],
+            ..Timer::default()
+        };
+
+        let filter = RecordFilter {
+            min_duration: Duration::from_secs(60),
+            ..RecordFilter::default()
+        };
+        let durations: Vec<Duration> = timer
+            .records_in_range(&filter)
+            .map(TimerRecord::duration)
+            .collect();
+
+        assert_eq!(durations, vec![Duration::from_secs(300)]);
+        // display only, nothing gets removed
+        assert_eq!(timer.records.len(), 2);
+    }
+
+    #[test]
+    fn test_records_in_range_since_until() {
+        let timer = Timer {
+            records: vec![record_at(8, 9), record_at(10, 11), record_at(12, 13)],
+            ..Timer::default()
+        };
+
+        let filter = RecordFilter {
+            since: Some(Utc.ymd(2022, 4, 1).and_hms(10, 0, 0)),
+            until: Some(Utc.ymd(2022, 4, 1).and_hms(12, 0, 0)),
+            ..RecordFilter::default()
+        };
+        let starts: Vec<u32> = timer
+            .records_in_range(&filter)
+            .map(|r| r.start.hour())
+            .collect();
+
+        assert_eq!(starts, vec![10]);
+    }
+
+    #[test]
+    fn test_clip_record() {
+        let record = record_at(8, 12);
+        let at = |hour| Utc.ymd(2022, 4, 1).and_hms(hour, 0, 0);
+
+        // straddling the start and the end of the range
+        assert_eq!(clip_record(&record, at(10), at(14)), Some(hours(2)));
+        assert_eq!(clip_record(&record, at(6), at(9)), Some(hours(1)));
+        assert_eq!(clip_record(&record, at(9), at(11)), Some(hours(2)));
+        assert_eq!(clip_record(&record, at(6), at(14)), Some(hours(4)));
+        assert_eq!(clip_record(&record, at(12), at(14)), None);
+        assert_eq!(clip_record(&record, at(4), at(8)), None);
+    }
+
+    #[test]
+    fn test_clipped_records() {
+        let timer = Timer {
+            records: vec![record_at(8, 11), record_at(12, 13), record_at(14, 18)],
+            ..Timer::default()
+        };
+        let filter = RecordFilter {
+            since: Some(Utc.ymd(2022, 4, 1).and_hms(10, 0, 0)),
+            until: Some(Utc.ymd(2022, 4, 1).and_hms(16, 0, 0)),
+            ..RecordFilter::default()
+        };
+
+        let clipped = filter.clipped_records(&timer.records);
+        let spans: Vec<(u32, u32)> = clipped
+            .iter()
+            .map(|r| (r.start.hour(), r.end.hour()))
+            .collect();
+        assert_eq!(spans, vec![(10, 11), (12, 13), (14, 16)]);
+
+        // without clipping the record straddling the start is left out and
+        // the one straddling the end counts fully
+        let whole: Duration = timer
+            .records_in_range(&filter)
+            .map(TimerRecord::duration)
+            .sum();
+        assert_eq!(whole, hours(5));
+        let clipped_total: Duration = clipped.iter().map(TimerRecord::duration).sum();
+        assert_eq!(clipped_total, hours(4));
+    }
+
+    #[test]
+    fn test_timer_names_sorted() {
+        let mut state = AppState::default();
+        for name in ["work", "hobby", "errands", "admin"] {
+            state.create_timer(name);
+        }
+
+        let names = state.timer_names_sorted();
+        assert_eq!(names, vec!["admin", "errands", "hobby", "work"]);
+        for _ in 0..10 {
+            assert_eq!(state.timer_names_sorted(), names);
+        }
+    }
+
+    #[test]
+    fn test_report_since_last_report() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.set_timer_active("work").unwrap();
+        state.get_timer_mut("work").unwrap().records = vec![record_at(8, 9)];
+
+        let mark = ReportCommand::parse_from(["report", "--mark"]);
+        let since_last = ReportCommand::parse_from(["report", "--since-last-report"]);
+        assert!(
+            !SubCommand::Report(ReportCommand::parse_from(["report", "--mark"])).is_read_only()
+        );
+
+        // without a marker everything is reported
+        let starts = |state: &AppState| -> Vec<u32> {
+            let filter = report_filter(
+                state,
+                &since_last,
+                StartOfWeek::Monday,
+                Utc.ymd(2022, 4, 1).and_hms(20, 0, 0),
+            )
+            .unwrap();
+            state
+                .get_timer("work")
+                .unwrap()
+                .records_in_range(&filter)
+                .map(|r| r.start.hour())
+                .collect()
+        };
+        assert_eq!(starts(&state), vec![8]);
+
+        let config = Config::default();
+        report(
+            &mut state,
+            &mark,
+            &config,
+            Utc.ymd(2022, 4, 1).and_hms(10, 0, 0),
+        )
+        .unwrap();
+        assert_eq!(
+            state.last_report_at,
+            Some(Utc.ymd(2022, 4, 1).and_hms(10, 0, 0))
+        );
+
+        state
+            .get_timer_mut("work")
+            .unwrap()
+            .records
+            .push(record_at(11, 12));
+        assert_eq!(starts(&state), vec![11]);
+    }
+
+    #[test]
+    fn test_records_with_running() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut timer = Timer {
+            records: vec![record_at(8, 9)],
+            ..Timer::default()
+        };
+        let total = |records: &[TimerRecord]| -> Duration {
+            records.iter().map(TimerRecord::duration).sum()
+        };
+
+        // nothing running, nothing to add
+        assert_eq!(total(&timer.records_with_running(now)), hours(1));
+
+        timer.start_timer(now - chrono::Duration::hours(2)).unwrap();
+        assert_eq!(total(&timer.records), hours(1));
+        assert_eq!(total(&timer.records_with_running(now)), hours(3));
+        // only a preview, the timer keeps running
+        assert!(timer.is_running());
+        assert_eq!(timer.records_len(), 1);
+    }
+
+    #[test]
+    fn test_record_count() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        assert_eq!(state.record_count(), 0);
+
+        state.create_timer("work");
+        state.create_timer("hobby");
+        for name in ["work", "work", "hobby"] {
+            let timer = state.get_timer_mut(name).unwrap();
+            timer.start_timer(now).unwrap();
+            timer.stop_timer(now, "".to_owned()).unwrap();
+        }
+        assert_eq!(state.record_count(), 3);
+
+        // running timers don't have a record yet
+        state
+            .get_timer_mut("hobby")
+            .unwrap()
+            .start_timer(now)
+            .unwrap();
+        assert_eq!(state.record_count(), 3);
+    }
+
+    #[test]
+    fn test_rename_tag() {
+        let tagged = |tags: &[&str]| {
+            let mut record = record_at(9, 10);
+            record.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            record
+        };
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.get_timer_mut("work").unwrap().records = vec![
+            tagged(&["dev", "bugfix"]),
+            tagged(&["bugfix", "fix"]),
+            tagged(&["meeting"]),
+        ];
+        state.get_timer_mut("hobby").unwrap().records = vec![tagged(&["bugfix"])];
+
+        assert_eq!(state.rename_tag("bugfix", "fix"), 3);
+
+        let tags = |name| -> Vec<Vec<String>> {
+            state
+                .get_timer(name)
+                .unwrap()
+                .records
+                .iter()
+                .map(|r| r.tags.clone())
+                .collect()
+        };
+        assert_eq!(
+            tags("work"),
+            [vec!["dev", "fix"], vec!["fix"], vec!["meeting"]]
+        );
+        assert_eq!(tags("hobby"), [vec!["fix"]]);
+    }
+
+    #[test]
+    fn test_remove_tag() {
+        let tagged = |tags: &[&str]| {
+            let mut record = record_at(9, 10);
+            record.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            record
+        };
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.get_timer_mut("work").unwrap().records = vec![
+            tagged(&["dev", "wip"]),
+            tagged(&["meeting"]),
+            tagged(&["wip"]),
+        ];
+        state.get_timer_mut("hobby").unwrap().records = vec![tagged(&["wip", "fun"])];
+        let tags = |state: &AppState, name| -> Vec<Vec<String>> {
+            state
+                .get_timer(name)
+                .unwrap()
+                .records
+                .iter()
+                .map(|r| r.tags.clone())
+                .collect()
+        };
+
+        assert_eq!(state.remove_tag("wip", Some("hobby")), Ok(1));
+        assert_eq!(tags(&state, "hobby"), [vec!["fun"]]);
+        assert_eq!(tags(&state, "work")[0], ["dev", "wip"]);
+
+        assert_eq!(state.remove_tag("wip", None), Ok(2));
+        assert_eq!(
+            tags(&state, "work"),
+            [vec!["dev"], vec!["meeting"], Vec::<&str>::new()]
+        );
+        assert_eq!(
+            state.remove_tag("wip", Some("missing")),
+            Err(AppError::NoSuchTimer)
+        );
+    }
+
+    #[test]
+    fn test_scoped_tag_totals() {
+        let tagged = |hour: u32, tags: &[&str]| {
+            let mut record = record_at(hour, hour + 1);
+            record.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            record
+        };
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.create_timer("idle");
+        state.get_timer_mut("work").unwrap().records =
+            vec![tagged(9, &["dev", "fix"]), tagged(10, &["meeting"])];
+        state.get_timer_mut("hobby").unwrap().records = vec![tagged(11, &["fix"])];
+        state.set_alias("w", "work").unwrap();
+        let totals = |names: &[&str]| {
+            let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+            scoped_tag_totals(&state, &names).map(|totals| {
+                totals
+                    .into_iter()
+                    .map(|(tag, duration)| (tag.to_owned(), duration))
+                    .collect::<Vec<_>>()
+            })
+        };
+        let entry = |tag: &str, duration| (tag.to_owned(), duration);
+
+        assert_eq!(
+            totals(&[]).unwrap(),
+            [
+                entry("dev", hours(1)),
+                entry("fix", hours(2)),
+                entry("meeting", hours(1))
+            ]
+        );
+        assert_eq!(totals(&["hobby"]).unwrap(), [entry("fix", hours(1))]);
+        // the same timer under its name and an alias counts once
+        assert_eq!(
+            totals(&["work", "w", "idle"]).unwrap(),
+            [
+                entry("dev", hours(1)),
+                entry("fix", hours(1)),
+                entry("meeting", hours(1))
+            ]
+        );
+        assert_eq!(totals(&["work", "hobby"]), totals(&[]));
+        assert_eq!(totals(&["missing"]), Err(AppError::NoSuchTimer));
+    }
+
+    #[test]
+    fn test_reset_all() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.get_timer_mut("work").unwrap().records = vec![record_at(8, 9), record_at(10, 11)];
+        state.get_timer_mut("hobby").unwrap().records = vec![record_at(19, 21)];
+        state.get_timer_mut("work").unwrap().adjustments = vec![Adjustment {
+            time: now,
+            seconds: 600,
+            comment: String::new(),
+        }];
+        state
+            .get_timer_mut("hobby")
+            .unwrap()
+            .start_timer(now)
+            .unwrap();
+
+        let cmd = ResetCommand {
+            all: true,
+            yes: false,
+            keep_timers: false,
+            delete_timers: false,
+        };
+        assert_eq!(
+            reset(&mut state, &cmd, |_| false).unwrap_err(),
+            AppError::NotConfirmed
+        );
+        assert_eq!(state.record_count(), 3);
+
+        reset(&mut state, &cmd, |_| true).unwrap();
+        assert_eq!(state.record_count(), 0);
+        assert!(!state.get_timer("hobby").unwrap().is_running());
+        assert_eq!(
+            state.get_timer("work").unwrap().total_duration(),
+            Duration::ZERO
+        );
+        assert_eq!(state.timer_names_sorted(), vec!["hobby", "work"]);
+
+        // --yes doesn't ask
+        state.get_timer_mut("work").unwrap().records = vec![record_at(8, 9)];
+        let cmd = ResetCommand { yes: true, ..cmd };
+        reset(&mut state, &cmd, |_| unreachable!()).unwrap();
+        assert_eq!(state.record_count(), 0);
+    }
+
+    fn reset_fixture() -> AppState {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.set_timer_active("work").unwrap();
+        let work = state.get_timer_mut("work").unwrap();
+        work.records = vec![record_at(8, 9)];
+        work.adjustments = vec![Adjustment {
+            time: Utc.ymd(2022, 4, 1).and_hms(12, 0, 0),
+            seconds: 600,
+            comment: String::new(),
+        }];
+        work.rate = Some(80.0);
+
+        state
+    }
+
+    #[test]
+    fn test_reset_keep_timers() {
+        let mut state = reset_fixture();
+        let cmd = ResetCommand {
+            all: false,
+            yes: false,
+            keep_timers: true,
+            delete_timers: false,
+        };
+
+        reset(&mut state, &cmd, |_| false).unwrap();
+        let work = state.get_timer("work").unwrap();
+        assert_eq!(work.records_len(), 0);
+        assert!(work.adjustments.is_empty());
+        assert_eq!(work.rate, Some(80.0));
+        assert_eq!(state.active_timer.as_deref(), Some("work"));
+
+        let cmd = ResetCommand {
+            all: true,
+            yes: true,
+            ..cmd
+        };
+        reset(&mut state, &cmd, |_| false).unwrap();
+        assert_eq!(state.timer_names_sorted(), vec!["hobby", "work"]);
+    }
+
+    #[test]
+    fn test_reset_delete_timers() {
+        let mut state = reset_fixture();
+        let cmd = ResetCommand {
+            all: false,
+            yes: false,
+            keep_timers: false,
+            delete_timers: true,
+        };
+
+        reset(&mut state, &cmd, |_| false).unwrap();
+        assert_eq!(state.timer_names_sorted(), vec!["hobby"]);
+        assert_eq!(state.active_timer, None);
+
+        let mut state = reset_fixture();
+        let cmd = ResetCommand {
+            all: true,
+            yes: true,
+            ..cmd
+        };
+        reset(&mut state, &cmd, |_| false).unwrap();
+        assert!(state.timers.is_empty());
+        assert_eq!(state.active_timer, None);
+    }
+
+    #[test]
+    fn test_last_record() {
+        let mut state = AppState::default();
+        assert!(state.last_record().is_none());
+
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.create_timer("empty");
+        // stored out of order, and a long record started early but ending last
+        state.get_timer_mut("work").unwrap().records = vec![record_at(14, 15), record_at(9, 10)];
+        state.get_timer_mut("hobby").unwrap().records = vec![record_at(8, 16), record_at(11, 12)];
+
+        let (name, record) = state.last_record().unwrap();
+        assert_eq!(name, "hobby");
+        assert_eq!(record.start, Utc.ymd(2022, 4, 1).and_hms(8, 0, 0));
+
+        state
+            .get_timer_mut("work")
+            .unwrap()
+            .records
+            .push(record_at(16, 17));
+        let (name, record) = state.last_record().unwrap();
+        assert_eq!(name, "work");
+        assert_eq!(record.end, Utc.ymd(2022, 4, 1).and_hms(17, 0, 0));
+    }
+
+    #[test]
+    fn test_first_record() {
+        let mut state = AppState::default();
+        assert!(state.first_record().is_none());
+
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.create_timer("empty");
+        state.get_timer_mut("work").unwrap().records = vec![record_at(14, 15), record_at(9, 17)];
+        state.get_timer_mut("hobby").unwrap().records = vec![record_at(11, 12), record_at(10, 11)];
+
+        let (name, record) = state.first_record().unwrap();
+        assert_eq!(name, "work");
+        assert_eq!(record.start, Utc.ymd(2022, 4, 1).and_hms(9, 0, 0));
+
+        // records starting at the same time go to the alphabetically first timer
+        state
+            .get_timer_mut("hobby")
+            .unwrap()
+            .records
+            .push(record_at(9, 10));
+        assert_eq!(state.first_record().unwrap().0, "hobby");
+    }
+
+    #[test]
+    fn test_grand_total() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.get_timer_mut("work").unwrap().records = vec![record_at(8, 10), record_at(14, 15)];
+        state.get_timer_mut("hobby").unwrap().records = vec![record_at(11, 12), record_at(18, 20)];
+
+        let names = ["hobby", "work"];
+        assert_eq!(
+            state.grand_total(&names, &RecordFilter::default(), false),
+            hours(6)
+        );
+        assert_eq!(
+            state.grand_total(&["work"], &RecordFilter::default(), false),
+            hours(3)
+        );
+
+        let filter = RecordFilter {
+            since: Some(Utc.ymd(2022, 4, 1).and_hms(9, 0, 0)),
+            until: Some(Utc.ymd(2022, 4, 1).and_hms(18, 0, 0)),
+            ..RecordFilter::default()
+        };
+        assert_eq!(state.grand_total(&names, &filter, false), hours(2));
+        // clipped, the hour of 8-10 within the range counts too
+        assert_eq!(state.grand_total(&names, &filter, true), hours(3));
+    }
+
+    fn filtered_names(includes: &[&str], excludes: &[&str]) -> Vec<String> {
+        let mut state = AppState::default();
+        for name in ["work", "hobby", "errands"] {
+            state.create_timer(name);
+        }
+        let owned =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|&n| n.to_owned()).collect() };
+
+        filter_timers(&state, &owned(includes), &owned(excludes))
+            .into_iter()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_timers_include() {
+        assert_eq!(filtered_names(&[], &[]), ["errands", "hobby", "work"]);
+        assert_eq!(
+            filtered_names(&["work", "hobby", "gone"], &[]),
+            ["hobby", "work"]
+        );
+    }
+
+    #[test]
+    fn test_filter_timers_exclude() {
+        assert_eq!(filtered_names(&[], &["hobby"]), ["errands", "work"]);
+    }
+
+    #[test]
+    fn test_filter_timers_include_and_exclude() {
+        // excludes win over includes
+        assert_eq!(filtered_names(&["work", "hobby"], &["hobby"]), ["work"]);
+    }
+
+    #[test]
+    fn test_filter_timers_alias() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.set_alias("job", "work").unwrap();
+        let owned = |name: &str| vec![name.to_owned()];
+
+        assert_eq!(filter_timers(&state, &owned("job"), &[]), ["work"]);
+        assert_eq!(filter_timers(&state, &[], &owned("job")), ["hobby"]);
+    }
+
+    #[test]
+    fn test_split_record() {
+        let mut record = record_at(9, 12);
+        record.comment = "Meetings".to_owned();
+        let mut timer = Timer {
+            records: vec![record_at(7, 8), record, record_at(13, 14)],
+            ..Timer::default()
+        };
+
+        timer
+            .split_record(1, Utc.ymd(2022, 4, 1).and_hms(10, 30, 0))
+            .unwrap();
+
+        let spans: Vec<(String, String)> = timer
+            .records
+            .iter()
+            .map(|r| {
+                (
+                    r.start.format("%H:%M").to_string(),
+                    r.end.format("%H:%M").to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("07:00", "08:00"),
+                ("09:00", "10:30"),
+                ("10:30", "12:00"),
+                ("13:00", "14:00")
+            ]
+            .map(|(start, end)| (start.to_owned(), end.to_owned()))
+        );
+        assert_eq!(timer.records[2].comment, "Meetings");
+        assert_eq!(timer.total_duration(), hours(5));
+    }
+
+    #[test]
+    fn test_split_record_out_of_bounds() {
+        let mut timer = Timer {
+            records: vec![record_at(9, 12)],
+            ..Timer::default()
+        };
+        let at = |hour| Utc.ymd(2022, 4, 1).and_hms(hour, 0, 0);
+
+        assert_eq!(
+            timer.split_record(0, at(8)),
+            Err(AppError::SplitOutsideRecord)
+        );
+        // the boundaries themselves would leave an empty record
+        assert_eq!(
+            timer.split_record(0, at(9)),
+            Err(AppError::SplitOutsideRecord)
+        );
+        assert_eq!(
+            timer.split_record(0, at(12)),
+            Err(AppError::SplitOutsideRecord)
+        );
+        assert_eq!(timer.split_record(1, at(10)), Err(AppError::NoSuchRecord));
+        assert_eq!(timer.records_len(), 1);
+    }
+
+    #[test]
+    fn test_merge_adjacent_records() {
+        let start = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+        let mut first = TimerRecord::new(
+            start,
+            start + chrono::Duration::minutes(20),
+            "Draft".to_owned(),
+        );
+        first.tags = vec!["writing".to_owned()];
+        let second_start = first.end + chrono::Duration::seconds(30);
+        let mut second = TimerRecord::new(
+            second_start,
+            second_start + chrono::Duration::minutes(10),
+            "Polish".to_owned(),
+        );
+        second.tags = vec!["writing".to_owned(), "review".to_owned()];
+
+        let mut timer = Timer {
+            records: vec![second, first, record_at(14, 15)],
+            ..Timer::default()
+        };
+
+        assert_eq!(timer.merge_adjacent_records(Duration::from_secs(60)), 1);
+        assert_eq!(timer.records_len(), 2);
+
+        let merged = &timer.records[0];
+        assert_eq!(merged.start, start);
+        assert_eq!(merged.end, second_start + chrono::Duration::minutes(10));
+        assert_eq!(merged.comment, "Draft; Polish");
+        assert_eq!(merged.tags, vec!["writing", "review"]);
+        // hours apart, untouched
+        assert_eq!(
+            timer.records[1].start,
+            Utc.ymd(2022, 4, 1).and_hms(14, 0, 0)
+        );
+        assert_eq!(timer.records[1].end, Utc.ymd(2022, 4, 1).and_hms(15, 0, 0));
+    }
+
+    #[test]
+    fn test_merge_adjacent_records_far_apart() {
+        let mut timer = Timer {
+            records: vec![record_at(8, 9), record_at(10, 11)],
+            ..Timer::default()
+        };
+
+        assert_eq!(timer.merge_adjacent_records(Duration::from_secs(30)), 0);
+        assert_eq!(timer.records_len(), 2);
+
+        // a record contained in another one keeps the later end
+        timer.records.push(record_at(8, 8));
+        timer.merge_adjacent_records(Duration::from_secs(30));
+        assert_eq!(timer.records[0].end, Utc.ymd(2022, 4, 1).and_hms(9, 0, 0));
+    }
+
+    #[test]
+    fn test_append_export_csv() {
+        let path = std::env::temp_dir().join(format!("mtt-test-{}-append.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.get_timer_mut("work").unwrap().records = vec![record_at(8, 9), record_at(10, 11)];
+        let filter = RecordFilter::default();
+
+        let append = |state: &AppState| {
+            append_export(state, &["work"], &filter, ExportFormat::Csv, &path).unwrap()
+        };
+        assert_eq!(append(&state), 2);
+
+        // only the record after the last exported one gets appended
+        state
+            .get_timer_mut("work")
+            .unwrap()
+            .records
+            .push(record_at(12, 13));
+        assert_eq!(append(&state), 1);
+        assert_eq!(append(&state), 0);
+
+        let rows = parse_csv(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let starts: Vec<&str> = rows.iter().map(|row| row[1].as_str()).collect();
+        assert_eq!(
+            starts,
+            vec![
+                "start",
+                "2022-04-01T08:00:00+00:00",
+                "2022-04-01T10:00:00+00:00",
+                "2022-04-01T12:00:00+00:00",
+            ]
+        );
+
+        assert!(append_export(&state, &["work"], &filter, ExportFormat::Json, &path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_json_string() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let timer = Timer::default();
+        let summary = TimerSummary::new("work", &timer, now);
+
+        let compact = json_string(&summary, false).unwrap();
+        let pretty = json_string(&summary, true).unwrap();
+
+        assert!(!compact.contains('\n'));
+        assert!(pretty.contains("\n  \"name\": \"work\""));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&compact).unwrap(),
+            serde_json::from_str::<serde_json::Value>(&pretty).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_export_csv_totals() {
+        let first = record_at(9, 10);
+        let second = record_at(11, 13);
+        let third = record_at(14, 15);
+        let records = [("work", &first), ("work", &second), ("hobby", &third)];
+
+        let mut out = vec![];
+        write_csv_with_totals(
+            &mut out,
+            records
+                .iter()
+                .map(|&(timer, record)| ExportRecord { timer, record }),
+        )
+        .unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[4], "Subtotal work,,,10800,,");
+        assert_eq!(lines[5], "Subtotal hobby,,,3600,,");
+
+        let total = first.duration() + second.duration() + third.duration();
+        assert_eq!(lines[6], format!("Total,,,{},,", total.as_secs()));
+    }
+
+    #[test]
+    fn test_export_csv_round_trip() {
+        let mut record = record_at(9, 10);
+        record.comment = "Review, \"part 1\"".to_owned();
+        record.tags = vec!["dev".to_owned(), "review".to_owned()];
+
+        let mut out = vec![];
+        write_csv(
+            &mut out,
+            [ExportRecord {
+                timer: "work",
+                record: &record,
+            }],
+        )
+        .unwrap();
+        let csv = String::from_utf8(out).unwrap();
+
+        let mut state = AppState::default();
+        import_csv(&mut state, &csv, None, Utc::now()).unwrap();
+        let imported = &state.get_timer("work").unwrap().records[0];
+
+        assert_eq!(imported.start, record.start);
+        assert_eq!(imported.end, record.end);
+        assert_eq!(imported.comment, record.comment);
+        assert_eq!(imported.tags, record.tags);
+    }
+
+    #[test]
+    fn test_resolve_timer_name() {
+        let mut state = AppState::default();
+        let no_selection = |_: &[&String]| -> Option<usize> { panic!("selector called") };
+
+        assert_eq!(
+            resolve_timer_name(&state, None, no_selection).unwrap_err(),
+            AppError::NoSuchTimer
+        );
+        assert_eq!(
+            resolve_timer_name(&state, Some("new"), no_selection).unwrap(),
+            Some("new".to_owned())
+        );
+
+        state.create_timer("work");
+        assert_eq!(
+            resolve_timer_name(&state, None, no_selection).unwrap(),
+            Some("work".to_owned())
+        );
+
+        // several timers ask the selector, which gets them sorted by name
+        state.create_timer("hobby");
+        let selected = resolve_timer_name(&state, None, |names| {
+            assert_eq!(names, [&"hobby".to_owned(), &"work".to_owned()]);
+            Some(1)
+        });
+        assert_eq!(selected.unwrap(), Some("work".to_owned()));
+
+        // selector declined, e.g. not a terminal
+        assert_eq!(
+            resolve_timer_name(&state, None, |_| None).unwrap_err(),
+            AppError::AmbiguousTimer
+        );
+
+        // the active timer is used as is
+        state.set_timer_active("hobby").unwrap();
+        assert_eq!(
+            resolve_timer_name(&state, None, no_selection).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_stop_comment_append_only() {
+        assert_eq!(
+            stop_comment(Some("Reviewing"), None, Some("PR #12")),
+            "Reviewing PR #12"
+        );
+        assert_eq!(stop_comment(None, None, Some("PR #12")), "PR #12");
+    }
+
+    #[test]
+    fn test_stop_comment_replace_only() {
+        assert_eq!(
+            stop_comment(Some("Reviewing"), Some("Meeting"), None),
+            "Meeting"
+        );
+        assert_eq!(stop_comment(Some("Reviewing"), None, None), "Reviewing");
+        assert_eq!(stop_comment(None, None, None), "");
+    }
+
+    #[test]
+    fn test_stop_comment_replace_and_append() {
+        assert_eq!(
+            stop_comment(Some("Reviewing"), Some("Meeting"), Some("with Bob")),
+            "Meeting with Bob"
+        );
+    }
+
+    #[test]
+    fn test_timer_description_round_trip() {
+        let mut state = AppState::default();
+        state.create_timer("acme");
+        let cmd = DescribeCommand {
+            timer_name: "acme".to_owned(),
+            description: Some("ACME Corp, https://tickets.example.com/42".to_owned()),
+        };
+        describe(&mut state, &cmd).unwrap();
+
+        let json = serde_json::to_string(&state).unwrap();
+        let state: AppState = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            state.get_timer("acme").unwrap().description.as_deref(),
+            Some("ACME Corp, https://tickets.example.com/42")
+        );
+
+        // state written before descriptions existed still loads
+        let old: Timer = serde_json::from_str(r#"{"records":[],"current_start":null}"#).unwrap();
+        assert!(old.description.is_none());
+    }
+
+    #[test]
+    fn test_render_list_description() {
+        let mut state = AppState::default();
+        state.create_timer("acme");
+        state.create_timer("hobby");
+        state.set_timer_active("hobby").unwrap();
+        state.get_timer_mut("acme").unwrap().description = Some("ACME Corp".to_owned());
+
+        assert_eq!(
+            render_list(&state, &sorted_timers(&state, ListSort::Name, false), false),
+            format!(
+                "  {:<20} {:<14} ACME Corp\n* {:<20} 0s\n",
+                "acme", "0s", "hobby"
+            )
+        );
+    }
+
+    /// Records on three days in two weeks and two months, with tags and comments
+    fn grouping_fixture() -> Vec<TimerRecord> {
+        let record = |day: u32, hours: i64, comment: &str, tags: &[&str]| {
+            let start = Utc.ymd(2022, 3, day).and_hms(12, 0, 0);
+            let mut record = TimerRecord::new(
+                start,
+                start + chrono::Duration::hours(hours),
+                comment.to_owned(),
+            );
+            record.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            record
+        };
+
+        vec![
+            // Monday, March 28th
+            record(28, 1, "Meeting", &["admin"]),
+            record(28, 2, "Coding", &["dev"]),
+            // Thursday, March 31st
+            record(31, 3, "Coding", &["dev", "review"]),
+            // Friday, April 1st, same week but next month
+            TimerRecord::new(
+                Utc.ymd(2022, 4, 1).and_hms(12, 0, 0),
+                Utc.ymd(2022, 4, 1).and_hms(16, 0, 0),
+                "".to_owned(),
+            ),
+            // Monday, March 21st, the week before
+            record(21, 5, "Meeting", &[]),
+        ]
+    }
+
+    fn hours(hours: u64) -> Duration {
+        Duration::from_secs(hours * 3600)
+    }
+
+    fn grouped_fixture(group_by: GroupBy) -> Vec<(String, Duration)> {
+        let records = grouping_fixture();
+        let records: Vec<&TimerRecord> = records.iter().collect();
+
+        grouped_totals(&records, group_by, DayBoundary::Local, StartOfWeek::Monday)
+    }
+
+    #[test]
+    fn test_weekly_totals_start_of_week() {
+        let at = |day| Utc.ymd(2022, 4, day).and_hms(12, 0, 0);
+        // Sunday, April 3rd and Monday, April 4th
+        let records = [
+            TimerRecord::new(at(3), at(3) + chrono::Duration::hours(1), "".to_owned()),
+            TimerRecord::new(at(4), at(4) + chrono::Duration::hours(2), "".to_owned()),
+        ];
+        let weeks = |start_of_week| {
+            weekly_totals(records.iter(), DayBoundary::Utc, start_of_week)
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            weeks(StartOfWeek::Monday),
+            vec![
+                (NaiveDate::from_ymd(2022, 3, 28), hours(1)),
+                (NaiveDate::from_ymd(2022, 4, 4), hours(2)),
+            ]
+        );
+        assert_eq!(
+            weeks(StartOfWeek::Sunday),
+            vec![(NaiveDate::from_ymd(2022, 4, 3), hours(3))]
+        );
+    }
+
+    #[test]
+    fn test_daily_totals_day_boundary() {
+        let late = TimerRecord::new(
+            Utc.ymd(2022, 4, 1).and_hms(23, 30, 0),
+            Utc.ymd(2022, 4, 2).and_hms(0, 30, 0),
+            "".to_owned(),
+        );
+        let day = |boundary| {
+            let totals = daily_totals(std::iter::once(&late), boundary);
+            totals.into_keys().collect::<Vec<_>>()
+        };
+
+        assert_eq!(day(DayBoundary::Utc), vec![NaiveDate::from_ymd(2022, 4, 1)]);
+        // local midnight follows the system timezone, so the record lands on
+        // the day after in timezones east of UTC
+        assert_eq!(
+            day(DayBoundary::Local),
+            vec![late.start.with_timezone(&Local).date().naive_local()]
+        );
+
+        assert_eq!(
+            DayBoundary::parse("+01:00").err(),
+            Some(AppError::InvalidDayBoundary("+01:00".to_owned()))
+        );
+        let offset = |input| DayBoundary::Offset(parse_tz_offset(input).unwrap());
+        assert_eq!(day(offset("+01:00")), vec![NaiveDate::from_ymd(2022, 4, 2)]);
+        assert_eq!(day(offset("+00:29")), vec![NaiveDate::from_ymd(2022, 4, 1)]);
+
+        let early = TimerRecord::new(
+            Utc.ymd(2022, 4, 2).and_hms(0, 15, 0),
+            Utc.ymd(2022, 4, 2).and_hms(1, 0, 0),
+            "".to_owned(),
+        );
+        let totals = daily_totals(std::iter::once(&early), offset("-05:30"));
+        assert_eq!(
+            totals.into_keys().collect::<Vec<_>>(),
+            vec![NaiveDate::from_ymd(2022, 4, 1)]
+        );
+    }
+
+    #[test]
+    fn test_parse_tz_offset() {
+        assert_eq!(
+            parse_tz_offset("+05:30"),
+            Ok(FixedOffset::east(5 * 3600 + 1800))
+        );
+        assert_eq!(parse_tz_offset("-08"), Ok(FixedOffset::west(8 * 3600)));
+        assert_eq!(parse_tz_offset("+00:00"), Ok(FixedOffset::east(0)));
+        for input in &["05:30", "+5:75", "+25:00", "", "+ab", "é"] {
+            assert_eq!(
+                parse_tz_offset(input),
+                Err(AppError::InvalidOffset(input.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_grouped_totals_day() {
+        assert_eq!(
+            grouped_fixture(GroupBy::Day),
+            vec![
+                ("2022-03-21".to_owned(), hours(5)),
+                ("2022-03-28".to_owned(), hours(3)),
+                ("2022-03-31".to_owned(), hours(3)),
+                ("2022-04-01".to_owned(), hours(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grouped_totals_week() {
+        assert_eq!(
+            grouped_fixture(GroupBy::Week),
+            vec![
+                ("Week of 2022-03-21".to_owned(), hours(5)),
+                ("Week of 2022-03-28".to_owned(), hours(10)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grouped_totals_month() {
+        assert_eq!(
+            grouped_fixture(GroupBy::Month),
+            vec![
+                ("2022-03".to_owned(), hours(11)),
+                ("2022-04".to_owned(), hours(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_monthly_totals_day_boundary() {
+        let record = |start: DateTime<Utc>, hours: i64| {
+            TimerRecord::new(start, start + chrono::Duration::hours(hours), String::new())
+        };
+        let records = [
+            // 00:30 on April 1st two hours east of UTC
+            record(Utc.ymd(2022, 3, 31).and_hms(22, 30, 0), 1),
+            // crosses midnight in UTC, counts towards the month it started in
+            record(Utc.ymd(2022, 4, 30).and_hms(22, 30, 0), 2),
+            record(Utc.ymd(2021, 12, 31).and_hms(12, 0, 0), 3),
+        ];
+        let totals = |day_boundary| {
+            monthly_totals(records.iter(), day_boundary)
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            totals(DayBoundary::Utc),
+            [
+                ((2021, 12), hours(3)),
+                ((2022, 3), hours(1)),
+                ((2022, 4), hours(2))
+            ]
+        );
+        assert_eq!(
+            totals(DayBoundary::Offset(FixedOffset::east(2 * 3600))),
+            [
+                ((2021, 12), hours(3)),
+                ((2022, 4), hours(1)),
+                ((2022, 5), hours(2))
+            ]
+        );
+        assert_eq!(
+            totals(DayBoundary::Offset(FixedOffset::west(3 * 3600))),
+            [
+                ((2021, 12), hours(3)),
+                ((2022, 3), hours(1)),
+                ((2022, 4), hours(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grouped_totals_tag() {
+        assert_eq!(
+            grouped_fixture(GroupBy::Tag),
+            vec![
+                ("admin".to_owned(), hours(1)),
+                ("dev".to_owned(), hours(5)),
+                ("review".to_owned(), hours(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grouped_totals_comment() {
+        assert_eq!(
+            grouped_fixture(GroupBy::Comment),
+            vec![
+                ("(no comment)".to_owned(), hours(4)),
+                ("Coding".to_owned(), hours(5)),
+                ("Meeting".to_owned(), hours(6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_round_duration() {
+        let unit = Duration::from_secs(15 * 60);
+
+        assert_eq!(round_duration(Duration::ZERO, unit), Duration::ZERO);
+        assert_eq!(round_duration(Duration::from_secs(60), unit), unit);
+        assert_eq!(round_duration(unit, unit), unit);
+        assert_eq!(
+            round_duration(unit + Duration::from_secs(1), unit),
+            unit * 2
+        );
+    }
+
+    #[test]
+    fn test_apply_daily_minimum() {
+        let at = |day, hour, minute| Utc.ymd(2022, 4, day).and_hms(hour, minute, 0);
+        let records = [
+            // a 5 minute first session and a longer one later that day
+            TimerRecord::new(at(1, 9, 0), at(1, 9, 5), "".to_owned()),
+            TimerRecord::new(at(1, 13, 0), at(1, 13, 10), "".to_owned()),
+            // the only record of the next day is long enough already
+            TimerRecord::new(at(2, 9, 0), at(2, 10, 0), "".to_owned()),
+        ];
+        let mut records: Vec<&TimerRecord> = records.iter().collect();
+        // the order records are stored in doesn't matter
+        records.swap(0, 1);
+        let minimum = Duration::from_secs(30 * 60);
+        let minutes = |billed: &[TimerRecord]| -> Vec<u64> {
+            billed
+                .iter()
+                .map(|record| record.duration().as_secs() / 60)
+                .collect()
+        };
+
+        let billed = apply_daily_minimum(&records, minimum, MinimumOn::First, DayBoundary::Utc);
+        assert_eq!(minutes(&billed), vec![10, 30, 60]);
+
+        let billed = apply_daily_minimum(&records, minimum, MinimumOn::Last, DayBoundary::Utc);
+        assert_eq!(minutes(&billed), vec![30, 5, 60]);
+    }
+
+    #[test]
+    fn test_render_report_table() {
+        let records = grouping_fixture();
+        let records: Vec<&TimerRecord> = records.iter().collect();
+        let (groups, total) = report_totals(
+            &records,
+            &[],
+            GroupBy::Day,
+            DayBoundary::Utc,
+            StartOfWeek::Monday,
+            None,
+        );
+        let counts = grouped_counts(
+            &records,
+            GroupBy::Day,
+            DayBoundary::Utc,
+            StartOfWeek::Monday,
+        );
+
+        let table = render_report_table(GroupBy::Day, &groups, &counts, total);
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(
+            lines,
+            [
+                "Day         Duration  Records",
+                "2022-03-21        5h        1",
+                "2022-03-28        3h        2",
+                "2022-03-31        3h        1",
+                "2022-04-01        4h        1",
+                "-----------------------------",
+                "Total            15h        5",
+            ]
+        );
+
+        let summed: Duration = groups.iter().map(|(_, duration)| *duration).sum();
+        assert_eq!(total, summed);
+    }
+
+    #[test]
+    fn test_render_day_matrix() {
+        let columns = [
+            (
+                "Acme, \"Inc\"",
+                vec![
+                    ("2022-04-01".to_owned(), hours(2)),
+                    ("2022-04-03".to_owned(), hours(1)),
+                ],
+            ),
+            ("hobby", vec![("2022-04-02".to_owned(), hours(3))]),
+        ];
+        let rows = parse_csv(&render_day_matrix(&columns)).unwrap();
+
+        assert_eq!(rows[0], ["day", "Acme, \"Inc\"", "hobby", "total"]);
+        assert_eq!(rows[1], ["2022-04-01", "7200", "0", "7200"]);
+        assert_eq!(rows[2][2], "10800");
+        assert_eq!(rows[3][0], "2022-04-03");
+        assert_eq!(rows[4], ["total", "10800", "10800", "21600"]);
+        assert_eq!(rows.len(), 5);
+    }
+
+    #[test]
+    fn test_report_totals_round_scope() {
+        let start = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let short = |offset: i64| {
+            let start = start + chrono::Duration::hours(offset);
+            TimerRecord::new(start, start + chrono::Duration::minutes(5), "".to_owned())
+        };
+        let records = [short(0), short(1), short(2)];
+        let records: Vec<&TimerRecord> = records.iter().collect();
+        let unit = Duration::from_secs(15 * 60);
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+
+        let (groups, total) = report_totals(
+            &records,
+            &[],
+            GroupBy::Day,
+            DayBoundary::Local,
+            StartOfWeek::Monday,
+            None,
+        );
+        assert_eq!(groups, vec![("2022-04-01".to_owned(), minutes(15))]);
+        assert_eq!(total, minutes(15));
+
+        // every 5 minute record is billed as 15 minutes
+        let (groups, total) = report_totals(
+            &records,
+            &[],
+            GroupBy::Day,
+            DayBoundary::Local,
+            StartOfWeek::Monday,
+            Some((unit, RoundScope::Record)),
+        );
+        assert_eq!(groups, vec![("2022-04-01".to_owned(), minutes(45))]);
+        assert_eq!(total, minutes(45));
+
+        // the day's 15 minutes are already a multiple of the unit
+        let (groups, total) = report_totals(
+            &records,
+            &[],
+            GroupBy::Day,
+            DayBoundary::Local,
+            StartOfWeek::Monday,
+            Some((unit, RoundScope::Total)),
+        );
+        assert_eq!(groups, vec![("2022-04-01".to_owned(), minutes(15))]);
+        assert_eq!(total, minutes(15));
+    }
+
+    #[test]
+    fn test_heatmap_level() {
+        let max = hours(8);
+
+        assert_eq!(heatmap_level(Duration::ZERO, max), 0);
+        assert_eq!(heatmap_level(Duration::from_secs(60), max), 1);
+        assert_eq!(heatmap_level(hours(2), max), 1);
+        assert_eq!(heatmap_level(hours(3), max), 2);
+        assert_eq!(heatmap_level(hours(5), max), 3);
+        assert_eq!(heatmap_level(max, max), 4);
+        assert_eq!(heatmap_level(Duration::ZERO, Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn test_render_week_comparison_increase() {
+        assert_eq!(
+            render_week_comparison(hours(5), hours(4)),
+            "This week: 5h\nLast week: 4h\nChange:    +1h (+25%)\n"
+        );
+    }
+
+    #[test]
+    fn test_render_week_comparison_decrease() {
+        assert_eq!(
+            render_week_comparison(hours(3), hours(4)),
+            "This week: 3h\nLast week: 4h\nChange:    -1h (-25%)\n"
+        );
+    }
+
+    #[test]
+    fn test_render_week_comparison_zero_baseline() {
+        assert_eq!(
+            render_week_comparison(hours(2), Duration::ZERO),
+            "This week: 2h\nLast week: 0s\nChange:    +2h (n/a)\n"
+        );
+        assert_eq!(
+            render_week_comparison(Duration::ZERO, Duration::ZERO),
+            "This week: 0s\nLast week: 0s\nChange:    +0s (n/a)\n"
+        );
+    }
+
+    #[test]
+    fn test_timer_color_from_name_is_stable() {
+        assert_eq!(TimerColor::from_name("work"), TimerColor::from_name("work"));
+        // fixed values, so colors don't change between releases
+        assert_eq!(TimerColor::from_name("work"), TimerColor::Palette(33));
+        assert_eq!(TimerColor::from_name("hobby"), TimerColor::Palette(167));
+    }
+
+    #[test]
+    fn test_timer_color() {
+        assert_eq!(TimerColor::parse("Blue"), Ok(TimerColor::Palette(4)));
+        assert_eq!(
+            TimerColor::parse("#3366ff"),
+            Ok(TimerColor::Rgb(0x33, 0x66, 0xff))
+        );
+        assert_eq!(
+            TimerColor::parse("#33669"),
+            Err(AppError::InvalidColor("#33669".to_owned()))
+        );
+        assert!(TimerColor::parse("purple-ish").is_err());
+
+        let mut timer = Timer::default();
+        assert_eq!(
+            TimerColor::of_timer("work", &timer),
+            TimerColor::from_name("work")
+        );
+        timer.color = Some("red".to_owned());
+        assert_eq!(TimerColor::of_timer("work", &timer), TimerColor::Palette(1));
+        assert_eq!(
+            TimerColor::Palette(1).paint("work"),
+            "\x1b[38;5;1mwork\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let now = Utc.ymd(2022, 4, 5).and_hms(12, 0, 0);
+
+        assert_eq!(
+            relative_time(now - chrono::Duration::minutes(4), now),
+            "4m ago"
+        );
+        assert_eq!(
+            relative_time(now - chrono::Duration::minutes(130), now),
+            "2h ago"
+        );
+        assert_eq!(
+            relative_time(now - chrono::Duration::days(3), now),
+            "3days ago"
+        );
+        assert_eq!(
+            relative_time(now + chrono::Duration::minutes(10), now),
+            "in 10m"
+        );
+    }
+
+    #[test]
+    fn test_format_record_line_relative() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let recent = record_at(9, 10);
+        let old = TimerRecord::new(
+            Utc.ymd(2022, 3, 28).and_hms(9, 0, 0),
+            Utc.ymd(2022, 3, 28).and_hms(10, 0, 0),
+            "".to_owned(),
+        );
+
+        assert!(format_record_line(0, &recent, Some(now), None)
+            .starts_with("   0            3h ago - "));
+        // older records keep their date
+        let absolute = old
+            .start
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string();
+        assert!(format_record_line(1, &old, Some(now), None).contains(&absolute));
+        assert_eq!(
+            format_record_line(1, &old, Some(now), None),
+            format_record_line(1, &old, None, None)
+        );
+    }
+
+    #[test]
+    fn test_format_record_line_without_comment() {
+        let record = record_at(9, 10);
+
+        assert!(format_record_line(0, &record, None, None).ends_with(" 1h"));
+        assert!(format_record_line(0, &record, None, Some(20)).ends_with(" 1h"));
+        assert!(format_search_match("work", 0, &record, None).ends_with(" 1h"));
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(
+            wrap_text("Reviewed the pull request about the new export formats", 20),
+            vec![
+                "Reviewed the pull",
+                "request about the",
+                "new export formats"
+            ]
+        );
+        assert_eq!(wrap_text("short", 20), vec!["short"]);
+        assert_eq!(wrap_text("", 20), vec![""]);
+        // words longer than a line are split
+        assert_eq!(
+            wrap_text("abcdefghij klm", 4),
+            vec!["abcd", "efgh", "ij", "klm"]
+        );
+    }
+
+    #[test]
+    fn test_format_record_line_wrapped() {
+        let mut record = record_at(9, 10);
+        record.comment = "Reviewed the pull request about the new export formats".to_owned();
+
+        let line = format_record_line(3, &record, None, Some(20));
+        let lines: Vec<&str> = line.lines().collect();
+        let indent = lines[0].len() - "Reviewed the pull".len();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("  Reviewed the pull"));
+        assert_eq!(lines[1], format!("{}request about the", " ".repeat(indent)));
+        assert_eq!(
+            lines[2],
+            format!("{}new export formats", " ".repeat(indent))
+        );
+    }
+
+    #[test]
+    fn test_render_goal_progress() {
+        // Friday, April 1st, the week started on March 28th
+        let now = Utc.ymd(2022, 4, 1).and_hms(18, 0, 0);
+        let mut timer = Timer {
+            records: vec![record_at(9, 12), record_at(13, 14)],
+            ..Timer::default()
+        };
+
+        assert_eq!(render_goal_progress(&timer, StartOfWeek::Monday, now), None);
+
+        timer.weekly_goal = Some(hours(8));
+        let goal = render_goal_progress(&timer, StartOfWeek::Monday, now).unwrap();
+        assert!(goal.contains("(50%)"), "{}", goal);
+        assert!(goal.ends_with("█████░░░░░"), "{}", goal);
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        assert_eq!(progress_bar(0.0, 4), "░░░░");
+        assert_eq!(progress_bar(0.5, 4), "██░░");
+        assert_eq!(progress_bar(1.7, 4), "████");
+    }
+
+    #[test]
+    fn test_total_seconds() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(18, 0, 0);
+        let mut timer = Timer {
+            records: vec![record_at(8, 9), record_at(10, 12)],
+            ..Timer::default()
+        };
+        let summed: u64 = timer.records.iter().map(|r| r.duration().as_secs()).sum();
+
+        assert_eq!(total_seconds(&timer, true, now), summed);
+
+        timer
+            .start_timer(now - chrono::Duration::minutes(5))
+            .unwrap();
+        assert_eq!(total_seconds(&timer, true, now), summed + 300);
+        assert_eq!(total_seconds(&timer, false, now), summed);
+    }
+
+    #[test]
+    fn test_render_short() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(18, 0, 0);
+        let mut timer = Timer {
+            records: vec![record_at(8, 9)],
+            ..Timer::default()
+        };
+
+        assert_eq!(render_short("proj", &timer, None, now), "proj: 1h");
+
+        timer
+            .start_timer(now - chrono::Duration::minutes(20))
+            .unwrap();
+        assert_eq!(
+            render_short("proj", &timer, None, now),
+            "proj: 1h 20m (running)"
+        );
+        assert_eq!(
+            render_short("proj", &timer, Some(1), now),
+            "proj: 1h (running)"
+        );
+    }
+
+    #[test]
+    fn test_recent_records() {
+        // stored out of order, as after importing older records
+        let timer = Timer {
+            records: vec![record_at(11, 12), record_at(8, 9), record_at(14, 15)],
+            ..Timer::default()
+        };
+        let indices = |count, reverse| -> Vec<usize> {
+            recent_records(&timer, count, reverse)
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect()
+        };
+
+        assert_eq!(indices(3, false), [1, 0, 2]);
+        assert_eq!(indices(3, true), [2, 0, 1]);
+        assert_eq!(indices(2, false), [0, 2]);
+        assert_eq!(indices(2, true), [2, 0]);
+        assert_eq!(indices(5, false), [1, 0, 2]);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(
+            sparkline(&[Duration::ZERO, hours(1), hours(2), hours(4), hours(7)]),
+            "▁▂▃▅█"
+        );
+        assert_eq!(sparkline(&[hours(8), hours(4), Duration::ZERO]), "█▅▁");
+        // nothing tracked, nothing to scale to
+        assert_eq!(sparkline(&[Duration::ZERO, Duration::ZERO]), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn test_should_colorize() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        for &terminal in &[true, false] {
+            assert!(should_colorize(ColorChoice::Always, env(&[]), terminal));
+            assert!(should_colorize(
+                ColorChoice::Always,
+                env(&[("NO_COLOR", "1")]),
+                terminal
+            ));
+            assert!(!should_colorize(ColorChoice::Never, env(&[]), terminal));
+            assert!(!should_colorize(
+                ColorChoice::Never,
+                env(&[("CLICOLOR_FORCE", "1")]),
+                terminal
+            ));
+            assert!(should_colorize(
+                ColorChoice::Auto,
+                env(&[("CLICOLOR_FORCE", "1")]),
+                terminal
+            ));
+            assert!(!should_colorize(
+                ColorChoice::Auto,
+                env(&[("NO_COLOR", "1")]),
+                terminal
+            ));
+        }
+
+        assert!(should_colorize(ColorChoice::Auto, env(&[]), true));
+        assert!(!should_colorize(ColorChoice::Auto, env(&[]), false));
+        assert!(!should_colorize(
+            ColorChoice::Auto,
+            env(&[("CLICOLOR_FORCE", "0")]),
+            false
+        ));
+        assert!(should_colorize(
+            ColorChoice::Auto,
+            env(&[("NO_COLOR", "")]),
+            true
+        ));
+        // forcing wins over NO_COLOR
+        assert!(should_colorize(
+            ColorChoice::Auto,
+            env(&[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]),
+            false
+        ));
+    }
+
+    #[test]
+    fn test_render_heatmap_no_color() {
+        let mut totals = BTreeMap::new();
+        totals.insert(NaiveDate::from_ymd(2022, 2, 1), hours(8));
+        totals.insert(NaiveDate::from_ymd(2022, 2, 2), hours(2));
+        // other months don't count towards the maximum
+        totals.insert(NaiveDate::from_ymd(2022, 3, 1), hours(20));
+
+        let heatmap = render_heatmap(
+            &totals,
+            parse_month("2022-02").unwrap(),
+            StartOfWeek::Monday,
+            false,
+        );
+        let lines: Vec<&str> = heatmap.lines().collect();
+
+        assert_eq!(lines[0], "February 2022");
+        assert_eq!(lines[2], "   ██ ░░ ·· ·· ·· ··");
+        assert_eq!(lines.len(), 2 + 5);
+        assert!(!heatmap.contains('\x1b'));
+    }
+
+    #[test]
+    fn test_render_heatmap_start_of_week_sunday() {
+        let totals = BTreeMap::new();
+        let heatmap = render_heatmap(
+            &totals,
+            parse_month("2022-05").unwrap(),
+            StartOfWeek::Sunday,
+            false,
+        );
+        let lines: Vec<&str> = heatmap.lines().collect();
+
+        // May 1st 2022 is a Sunday, so the first week is complete
+        assert_eq!(lines[1], "Su Mo Tu We Th Fr Sa");
+        assert_eq!(lines[2], "·· ·· ·· ·· ·· ·· ··");
+        assert_eq!(lines.len(), 2 + 5);
+    }
+
+    #[test]
+    fn test_timer_records_len() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut timer = Timer::default();
+        assert_eq!(timer.records_len(), 0);
+
+        for _ in 0..3 {
+            timer.start_timer(now).unwrap();
+            timer.stop_timer(now, "".to_owned()).unwrap();
+        }
+        assert_eq!(timer.records_len(), 3);
+
+        timer.records.remove(1);
+        assert_eq!(timer.records_len(), 2);
+
+        let summary = serde_json::to_value(TimerSummary::new("work", &timer, now)).unwrap();
+        assert_eq!(summary["record_count"], 2);
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("acme corp"), "acme corp");
+        assert_eq!(sanitize_filename("client/project"), "client_project");
+        assert_eq!(sanitize_filename("../etc"), "_etc");
+        assert_eq!(sanitize_filename(".."), "_");
+    }
+
+    #[test]
+    fn test_export_to_dir() {
+        let dir = std::env::temp_dir().join(format!("mtt-test-{}-export", std::process::id()));
+        let mut state = AppState::default();
+        for name in ["work", "client/a", "client:a"] {
+            state.create_timer(name);
+            state
+                .get_timer_mut(name)
+                .unwrap()
+                .records
+                .push(record_at(9, 10));
+        }
+
+        let paths = export_to_dir(
+            &state,
+            &["client/a", "client:a", "work"],
+            &RecordFilter::default(),
+            ExportFormat::Json,
+            false,
+            &dir,
+        )
+        .unwrap();
+
+        assert_eq!(
+            paths,
+            vec![
+                dir.join("client_a.json"),
+                dir.join("client_a-2.json"),
+                dir.join("work.json")
+            ]
+        );
+        for (path, name) in paths.iter().zip(["client/a", "client:a", "work"]) {
+            let exported: serde_json::Value =
+                serde_json::from_reader(File::open(path).unwrap()).unwrap();
+            assert_eq!(exported[0]["timer"], name);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn stop_command(timer_name: Option<&str>) -> StopCommand {
+        StopCommand {
+            timer_name: timer_name.map(str::to_owned),
+            stop_time: None,
+            comment: None,
+            comment_from_file: None,
+            append_comment: None,
+            comment_template: None,
+            comment_required: false,
+            rate: None,
+            discard_if_shorter: None,
+            all: false,
+            keep_running: false,
+        }
+    }
+
+    #[test]
+    fn test_stop_alias_comment_template() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.set_alias("w", "work").unwrap();
+        state
+            .get_timer_mut("work")
+            .unwrap()
+            .start_timer(now)
+            .unwrap();
+
+        let cmd = StopCommand {
+            comment_template: Some("{timer}".to_owned()),
+            ..stop_command(Some("w"))
+        };
+        stop(&mut state, &cmd, &Config::default(), now).unwrap();
+
+        assert_eq!(state.get_timer("work").unwrap().records[0].comment, "work");
+    }
+
+    #[test]
+    fn test_stop_named_timer_not_running() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        state.create_timer("foo");
+        state.create_timer("bar");
+        start(
+            &mut state,
+            &start_command("bar", false, false),
+            &Config::default(),
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(
+            stop(
+                &mut state,
+                &stop_command(Some("foo")),
+                &Config::default(),
+                now
+            )
+            .unwrap_err(),
+            AppError::TimerNotRunning("foo".to_owned())
+        );
+        assert_eq!(
+            stop(
+                &mut state,
+                &stop_command(Some("baz")),
+                &Config::default(),
+                now
+            )
+            .unwrap_err(),
+            AppError::NoSuchTimer
+        );
+
+        // bar keeps running and stays active
+        assert!(state.get_timer("bar").unwrap().is_running());
+        assert_eq!(state.active_timer.as_deref(), Some("bar"));
+        assert_eq!(state.get_timer("foo").unwrap().records_len(), 0);
+
+        stop(
+            &mut state,
+            &stop_command(Some("bar")),
+            &Config::default(),
+            now,
+        )
+        .unwrap();
+        assert_eq!(state.get_timer("bar").unwrap().records_len(), 1);
+
+        assert_eq!(
+            stop(&mut state, &stop_command(None), &Config::default(), now).unwrap_err(),
+            AppError::NoTimerRunning
+        );
+    }
+
+    #[test]
+    fn test_stop_comment_required() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        start(
+            &mut state,
+            &start_command("work", true, false),
+            &Config::default(),
+            now,
+        )
+        .unwrap();
+
+        let mut cmd = stop_command(None);
+        cmd.comment_required = true;
+        cmd.comment = Some(" ".to_owned());
+        assert_eq!(
+            stop(&mut state, &cmd, &Config::default(), now).unwrap_err(),
+            AppError::CommentRequired
+        );
+        assert!(state.get_timer("work").unwrap().is_running());
+
+        cmd.comment = Some("Planning".to_owned());
+        stop(&mut state, &cmd, &Config::default(), now).unwrap();
+        assert_eq!(
+            state.get_timer("work").unwrap().records[0].comment,
+            "Planning"
+        );
+    }
+
+    #[test]
+    fn test_stop_comment_required_config() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let config = Config {
+            comment_required: true,
+            ..Config::default()
+        };
+        let mut state = AppState::default();
+        start(
+            &mut state,
+            &start_command("work", true, false),
+            &config,
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(
+            stop(&mut state, &stop_command(None), &config, now).unwrap_err(),
+            AppError::CommentRequired
+        );
+
+        // a comment given on start counts
+        state.get_timer_mut("work").unwrap().current_comment = Some("Planning".to_owned());
+        stop(&mut state, &stop_command(None), &config, now).unwrap();
+    }
+
+    #[test]
+    fn test_expand_comment_template() {
+        let record = TimerRecord::new(
+            Utc.ymd(2022, 4, 1).and_hms(9, 0, 0),
+            Utc.ymd(2022, 4, 1).and_hms(10, 30, 0),
+            "".to_owned(),
+        );
+        let date = record
+            .start
+            .with_timezone(&Local)
+            .format("%Y-%m-%d")
+            .to_string();
+
+        assert_eq!(expand_comment_template("{date}", "work", &record), date);
+        assert_eq!(expand_comment_template("{timer}", "work", &record), "work");
+        assert_eq!(
+            expand_comment_template("{duration}", "work", &record),
+            "1h 30m"
+        );
+        assert_eq!(
+            expand_comment_template("{date}: worked on {timer} for {duration}", "work", &record),
+            format!("{}: worked on work for 1h 30m", date)
+        );
+        assert_eq!(
+            expand_comment_template("{unknown}", "work", &record),
+            "{unknown}"
+        );
+    }
+
+    #[test]
+    fn test_stop_comment_template() {
+        let start_time = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+        let config = Config {
+            comment_default: Some("done".to_owned()),
+            ..Config::default()
+        };
+        let mut state = AppState::default();
+        start(
+            &mut state,
+            &start_command("work", true, false),
+            &config,
+            start_time,
+        )
+        .unwrap();
+
+        let mut cmd = stop_command(None);
+        cmd.comment_template = Some("{timer} for {duration}".to_owned());
+        stop(
+            &mut state,
+            &cmd,
+            &config,
+            start_time + chrono::Duration::hours(2),
+        )
+        .unwrap();
+
+        assert_eq!(
+            state.get_timer("work").unwrap().records[0].comment,
+            "work for 2h"
+        );
+    }
+
+    #[test]
+    fn test_stop_message() {
+        let record = TimerRecord::new(
+            Utc.ymd(2022, 4, 1).and_hms(9, 0, 0),
+            Utc.ymd(2022, 4, 1).and_hms(10, 25, 0),
+            "".to_owned(),
+        );
+
+        assert_eq!(
+            stop_message(&record, hours(12)),
+            "Timer stopped after 1h 25m\nTotal: 12h"
+        );
+    }
+
+    #[test]
+    fn test_stop_all() {
+        let start_time = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let stop_time = start_time + chrono::Duration::hours(1);
+        let mut state = AppState::default();
+        for name in &["work", "hobby", "idle"] {
+            state.create_timer(name);
+        }
+        // only one timer can be started through the commands, so set up the second one directly
+        state
+            .get_timer_mut("work")
+            .unwrap()
+            .start_timer(start_time)
+            .unwrap();
+        state
+            .get_timer_mut("hobby")
+            .unwrap()
+            .start_timer(start_time)
+            .unwrap();
+        state.set_timer_active("work").unwrap();
+
+        let mut cmd = stop_command(None);
+        cmd.all = true;
+        cmd.comment = Some("End of day".to_owned());
+        stop(&mut state, &cmd, &Config::default(), stop_time).unwrap();
+
+        for name in &["work", "hobby"] {
+            let timer = state.get_timer(name).unwrap();
+            assert!(!timer.is_running());
+            assert_eq!(timer.records_len(), 1);
+            assert_eq!(timer.records[0].comment, "End of day");
+        }
+        assert_eq!(state.get_timer("idle").unwrap().records_len(), 0);
+
+        // nothing running is fine
+        stop(&mut state, &cmd, &Config::default(), stop_time).unwrap();
+    }
+
+    #[test]
+    fn test_stop_time_before_start() {
+        let start_time = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state
+            .get_timer_mut("work")
+            .unwrap()
+            .start_timer(start_time)
+            .unwrap();
+        state.set_timer_active("work").unwrap();
+
+        let mut cmd = stop_command(None);
+        cmd.stop_time = Some("1h ago".to_owned());
+        let now = start_time + chrono::Duration::minutes(30);
+        assert_eq!(
+            stop(&mut state, &cmd, &Config::default(), now),
+            Err(AppError::EndBeforeStart)
+        );
+        let timer = state.get_timer("work").unwrap();
+        assert!(timer.is_running());
+        assert_eq!(timer.records_len(), 0);
+
+        // stopping right at the start is fine
+        cmd.stop_time = Some("30m ago".to_owned());
+        stop(&mut state, &cmd, &Config::default(), now).unwrap();
+        assert_eq!(state.get_timer("work").unwrap().records_len(), 1);
+    }
+
+    #[test]
+    fn test_stop_keep_running() {
+        let start_time = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+        let checkpoint_time = Utc.ymd(2022, 4, 1).and_hms(13, 0, 0);
+        let mut state = AppState::default();
+        state.create_timer("work");
+        let timer = state.get_timer_mut("work").unwrap();
+        timer.start_timer(start_time).unwrap();
+        timer.current_comment = Some("Marathon".to_owned());
+        state.set_timer_active("work").unwrap();
+
+        let mut cmd = stop_command(None);
+        cmd.keep_running = true;
+        stop(&mut state, &cmd, &Config::default(), checkpoint_time).unwrap();
+
+        let timer = state.get_timer("work").unwrap();
+        assert_eq!(timer.records_len(), 1);
+        assert_eq!(timer.records[0].start, start_time);
+        assert_eq!(timer.records[0].end, checkpoint_time);
+        assert_eq!(timer.records[0].comment, "Marathon");
+        assert!(timer.is_running());
+        assert_eq!(timer.current_start, Some(checkpoint_time));
+        assert_eq!(timer.current_comment.as_deref(), Some("Marathon"));
+
+        // the final stop continues seamlessly from the checkpoint
+        cmd.keep_running = false;
+        let stop_time = checkpoint_time + chrono::Duration::hours(1);
+        stop(&mut state, &cmd, &Config::default(), stop_time).unwrap();
+        let timer = state.get_timer("work").unwrap();
+        assert_eq!(timer.records[1].start, checkpoint_time);
+        assert_eq!(timer.total_duration(), hours(5));
+    }
+
+    #[test]
+    fn test_auto_checkpoint_only_on_writing_commands() {
+        let dir = std::env::temp_dir().join(format!("mtt-test-{}-checkpoint", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let state_path = dir.join("state.json");
+        let lock_path = dir.join("state.json.lock");
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let config = Config {
+            auto_checkpoint: Some("1h".to_owned()),
+            ..Config::default()
+        };
+
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.set_timer_active("work").unwrap();
+        state
+            .get_timer_mut("work")
+            .unwrap()
+            .start_timer(now - chrono::Duration::hours(2))
+            .unwrap();
+        state.write_to_file(&state_path, false).unwrap();
+        let before = std::fs::read_to_string(&state_path).unwrap();
+
+        let run = |args: &[&str]| {
+            let opts = Opts::try_parse_from(args).unwrap();
+            execute(
+                &opts,
+                &config,
+                false,
+                &state_path,
+                &dir.join("config.json"),
+                now,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(run(&["mtt", "running"]), 0);
+        assert!(!lock_path.exists());
+        assert_eq!(std::fs::read_to_string(&state_path).unwrap(), before);
+
+        assert_eq!(run(&["mtt", "pin", "work"]), 0);
+        assert!(!lock_path.exists());
+        let state = AppState::read_from_file(&state_path, true).unwrap();
+        let timer = state.get_timer("work").unwrap();
+        assert_eq!(timer.records_len(), 1);
+        assert_eq!(timer.current_start, Some(now));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_maybe_checkpoint() {
+        let start_time = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("idle");
+        let timer = state.get_timer_mut("work").unwrap();
+        timer.start_timer(start_time).unwrap();
+        timer.current_comment = Some("Marathon".to_owned());
+
+        let now = start_time + chrono::Duration::minutes(59);
+        assert_eq!(state.maybe_checkpoint(now, hours(1)), 0);
+        assert_eq!(state.get_timer("work").unwrap().records_len(), 0);
+
+        let now = start_time + chrono::Duration::minutes(61);
+        assert_eq!(state.maybe_checkpoint(now, hours(1)), 1);
+        let timer = state.get_timer("work").unwrap();
+        assert_eq!(timer.records_len(), 1);
+        assert_eq!(timer.records[0].start, start_time);
+        assert_eq!(timer.records[0].end, now);
+        assert_eq!(timer.records[0].comment, "Marathon");
+        assert_eq!(timer.current_start, Some(now));
+        assert!(state.get_timer("idle").unwrap().records.is_empty());
+
+        // the next checkpoint is due an interval later
+        assert_eq!(
+            state.maybe_checkpoint(now + chrono::Duration::hours(1), hours(1)),
+            0
+        );
+    }
+
+    #[test]
+    fn test_stop_comment_from_file() {
+        let start_time = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+        let stop_time = Utc.ymd(2022, 4, 1).and_hms(10, 0, 0);
+        let path =
+            std::env::temp_dir().join(format!("mtt-test-{}-comment.txt", std::process::id()));
+        std::fs::write(&path, "Reviewed the parser\n\nFound two bugs\n").unwrap();
+
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state
+            .get_timer_mut("work")
+            .unwrap()
+            .start_timer(start_time)
+            .unwrap();
+        state.set_timer_active("work").unwrap();
+
+        let mut cmd = stop_command(None);
+        cmd.comment_from_file = Some(path.with_extension("missing"));
+        let err = stop(&mut state, &cmd, &Config::default(), stop_time).unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::CannotReadComment(_, ErrorKind::NotFound)
+        ));
+        assert!(state.get_timer("work").unwrap().is_running());
+
+        cmd.comment_from_file = Some(path.clone());
+        stop(&mut state, &cmd, &Config::default(), stop_time).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let timer = state.get_timer("work").unwrap();
+        assert_eq!(
+            timer.records[0].comment,
+            "Reviewed the parser\n\nFound two bugs"
+        );
+    }
+
+    #[test]
+    fn test_stop_discard_if_shorter() {
+        let start_time = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let config = Config::default();
+        let mut state = AppState::default();
+        let mut cmd = stop_command(None);
+        cmd.discard_if_shorter = Some(Duration::from_secs(60));
+
+        // just under the threshold, the interval is dropped
+        start(
+            &mut state,
+            &start_command("work", true, false),
+            &config,
+            start_time,
+        )
+        .unwrap();
+        stop(
+            &mut state,
+            &cmd,
+            &config,
+            start_time + chrono::Duration::seconds(59),
+        )
+        .unwrap();
+        let timer = state.get_timer("work").unwrap();
+        assert!(!timer.is_running());
+        assert_eq!(timer.records_len(), 0);
+
+        // exactly at the threshold it is kept
+        start(
+            &mut state,
+            &start_command("work", false, false),
+            &config,
+            start_time,
+        )
+        .unwrap();
+        stop(
+            &mut state,
+            &cmd,
+            &config,
+            start_time + chrono::Duration::seconds(60),
+        )
+        .unwrap();
+        let timer = state.get_timer("work").unwrap();
+        assert_eq!(timer.records_len(), 1);
+        assert_eq!(timer.records[0].duration(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_stop_comment_default_config() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let config = Config {
+            comment_default: Some("done".to_owned()),
+            ..Config::default()
+        };
+        let mut state = AppState::default();
+
+        start(
+            &mut state,
+            &start_command("work", true, false),
+            &config,
+            now,
+        )
+        .unwrap();
+        stop(&mut state, &stop_command(None), &config, now).unwrap();
+
+        start(
+            &mut state,
+            &start_command("work", false, false),
+            &config,
+            now,
+        )
+        .unwrap();
+        let mut cmd = stop_command(None);
+        cmd.comment = Some("Planning".to_owned());
+        stop(&mut state, &cmd, &config, now).unwrap();
+
+        let comments: Vec<&str> = state
+            .get_timer("work")
+            .unwrap()
+            .records
+            .iter()
+            .map(|r| r.comment.as_str())
+            .collect();
+        assert_eq!(comments, vec!["done", "Planning"]);
+    }
+
+    #[test]
+    fn test_write_jsonl() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.get_timer_mut("work").unwrap().records = vec![record_at(9, 10), record_at(11, 12)];
+        state.get_timer_mut("hobby").unwrap().records = vec![record_at(19, 21)];
+
+        let filter = RecordFilter::default();
+        let mut out = vec![];
+        write_jsonl(
+            &mut out,
+            export_records(&state, &["hobby", "work"], &filter),
+        )
+        .unwrap();
+        let output = String::from_utf8(out).unwrap();
+
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["timer"], "hobby");
+        assert_eq!(lines[2]["timer"], "work");
+        assert_eq!(lines[2]["start"], "2022-04-01T11:00:00Z");
+    }
+
+    #[test]
+    fn test_write_ndjson() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.get_timer_mut("work").unwrap().records = vec![record_at(9, 10), record_at(11, 13)];
+
+        let filter = RecordFilter::default();
+        let mut out = vec![];
+        write_ndjson(&mut out, export_records(&state, &["work"], &filter)).unwrap();
+        let output = String::from_utf8(out).unwrap();
+
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        for (line, record) in lines.iter().zip(&state.get_timer("work").unwrap().records) {
+            assert_eq!(line["schema_version"], NDJSON_SCHEMA_VERSION);
+            assert_eq!(line["timer"], "work");
+            assert_eq!(
+                line["start"],
+                record.start.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+            );
+            assert_eq!(line["duration_seconds"], record.duration().as_secs());
+            let local =
+                |field: &str| DateTime::parse_from_rfc3339(line[field].as_str().unwrap()).unwrap();
+            assert_eq!(local("start_local"), record.start);
+            assert_eq!(local("end_local"), record.end);
+        }
+        assert_eq!(lines[1]["duration_seconds"], 7200);
+    }
+
+    #[test]
+    fn test_export_comment_grep() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        let commented = |start_h, comment: &str| {
+            let mut record = record_at(start_h, start_h + 1);
+            record.comment = comment.to_owned();
+            record
+        };
+        state.get_timer_mut("work").unwrap().records = vec![
+            commented(9, "ACME: kickoff"),
+            commented(11, "Internal meeting"),
+            commented(14, "acme: review"),
+        ];
+        state.get_timer_mut("hobby").unwrap().records = vec![commented(19, "ACME: side project")];
+
+        let filter = RecordFilter {
+            since: Some(Utc.ymd(2022, 4, 1).and_hms(10, 0, 0)),
+            comment: Some(CommentMatcher::new("(?i)^acme:", true, false).unwrap()),
+            ..RecordFilter::default()
+        };
+        let mut out = vec![];
+        write_csv(
+            &mut out,
+            export_records(&state, &["hobby", "work"], &filter),
+        )
+        .unwrap();
+        let csv = String::from_utf8(out).unwrap();
+
+        // the header, the hobby record and the later work record
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.contains("acme: review"));
+        assert!(!csv.contains("kickoff"));
+    }
+
+    fn search_matches(
+        query: &str,
+        regex: bool,
+        ignore_case: bool,
+        invert: bool,
+    ) -> Vec<&'static str> {
+        let comments = ["Fixed bug #12", "Meeting", "", "fixing more BUGS"];
+        let predicate = SearchPredicate {
+            matcher: CommentMatcher::new(query, regex, ignore_case).unwrap(),
+            invert,
+            filter: RecordFilter::default(),
+        };
+
+        comments
+            .iter()
+            .copied()
+            .filter(|&comment| {
+                predicate.matches(&TimerRecord::new(
+                    Utc::now(),
+                    Utc::now(),
+                    comment.to_owned(),
+                ))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_export_totals_need_csv() {
+        let state = AppState::default();
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let cmd =
+            ExportCommand::try_parse_from(["export", "--format", "json", "--totals"]).unwrap();
+
+        let err = export(&state, &cmd, &Config::default(), now).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&AppError::TotalsNeedCsv));
+    }
+
+    #[test]
+    fn test_search_timers() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state.set_alias("job", "work").unwrap();
+        let timers = |args: &[&str]| {
+            let cmd = SearchCommand::try_parse_from(["search", "bug"].iter().chain(args))?;
+            Ok::<_, clap::Error>(search_timers(&state, &cmd))
+        };
+
+        assert_eq!(timers(&["--timer", "job"]).unwrap(), Ok(vec!["work"]));
+        assert_eq!(
+            timers(&["--timer", "gone"]).unwrap(),
+            Err(AppError::NoSuchTimer)
+        );
+        assert_eq!(timers(&["--exclude", "job"]).unwrap(), Ok(vec!["hobby"]));
+        assert!(timers(&["--timer", "work", "--include", "hobby"]).is_err());
+        assert!(timers(&["--timer", "work", "--exclude", "hobby"]).is_err());
+    }
+
+    #[test]
+    fn test_search_range() {
+        let predicate = |invert| SearchPredicate {
+            matcher: CommentMatcher::new("bug", false, false).unwrap(),
+            invert,
+            filter: RecordFilter {
+                since: Some(Utc.ymd(2022, 4, 1).and_hms(10, 0, 0)),
+                until: Some(Utc.ymd(2022, 4, 1).and_hms(14, 0, 0)),
+                ..RecordFilter::default()
+            },
+        };
+        let record = |start_h, comment: &str| {
+            let mut record = record_at(start_h, start_h + 1);
+            record.comment = comment.to_owned();
+            record
+        };
+
+        assert!(predicate(false).matches(&record(11, "Fixed bug #12")));
+        assert!(!predicate(false).matches(&record(8, "Fixed bug #12")));
+        assert!(!predicate(false).matches(&record(14, "Fixed bug #12")));
+        assert!(predicate(true).matches(&record(11, "Meeting")));
+        assert!(!predicate(true).matches(&record(8, "Meeting")));
+    }
+
+    #[test]
+    fn test_search_substring() {
+        assert_eq!(
+            search_matches("bug", false, false, false),
+            vec!["Fixed bug #12"]
+        );
+        assert_eq!(
+            search_matches("bug", false, true, false),
+            vec!["Fixed bug #12", "fixing more BUGS"]
+        );
+    }
+
+    #[test]
+    fn test_search_inverted_substring() {
+        assert_eq!(
+            search_matches("bug", false, false, true),
+            vec!["Meeting", "", "fixing more BUGS"]
+        );
+        assert_eq!(
+            search_matches("bug", false, true, true),
+            vec!["Meeting", ""]
+        );
+    }
+
+    #[test]
+    fn test_search_inverted_regex() {
+        assert_eq!(
+            search_matches(r"#\d+", true, false, false),
+            vec!["Fixed bug #12"]
+        );
+        assert_eq!(
+            search_matches("^fix", true, false, true),
+            vec!["Fixed bug #12", "Meeting", ""]
+        );
+        assert_eq!(
+            search_matches("^fix", true, true, true),
+            vec!["Meeting", ""]
+        );
+        // empty comments are found with an inverted match-anything-but-empty pattern
+        assert_eq!(search_matches(".", true, false, true), vec![""]);
+    }
+
+    #[test]
+    fn test_search_highlight() {
+        let mut record = record_at(9, 10);
+        record.comment = "Fixed Bug #12, then another bug".to_owned();
+        let matcher = CommentMatcher::new("bug", false, true).unwrap();
+
+        let line = format_search_match("work", 0, &record, Some(&matcher));
+        assert!(line.ends_with("Fixed \x1b[1;31mBug\x1b[0m #12, then another \x1b[1;31mbug\x1b[0m"));
+        assert!(line.starts_with("work     0  "));
+
+        let plain = format_search_match("work", 0, &record, None);
+        assert!(plain.ends_with("  Fixed Bug #12, then another bug"));
+        assert!(!plain.contains('\x1b'));
+
+        let regex = CommentMatcher::new(r"#\d+", true, false).unwrap();
+        let ranges = regex.find_ranges(&record.comment);
+        assert_eq!(&record.comment[ranges[0].clone()], "#12");
+        assert_eq!(ranges.len(), 1);
+    }
+
+    #[test]
+    fn test_search_invalid_regex() {
+        assert!(matches!(
+            CommentMatcher::new("(", true, false),
+            Err(AppError::InvalidRegex(_))
+        ));
+    }
+
+    #[test]
+    fn test_running_exit_code() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        assert_eq!(running_exit_code(&state), 1);
+
+        start(
+            &mut state,
+            &start_command("work", true, false),
+            &Config::default(),
+            now,
+        )
+        .unwrap();
+        assert_eq!(running_exit_code(&state), 0);
+
+        stop(&mut state, &stop_command(None), &Config::default(), now).unwrap();
+        assert_eq!(running_exit_code(&state), 1);
+    }
+
+    #[test]
+    fn test_active_record_preview() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.set_timer_active("work").unwrap();
+        assert!(state.active_record_preview(now).is_none());
+
+        let timer = state.get_active_timer_mut().unwrap();
+        timer
+            .start_timer(now - chrono::Duration::minutes(42))
+            .unwrap();
+        timer.current_comment = Some("Planning".to_owned());
+
+        let preview = state.active_record_preview(now).unwrap();
+        assert_eq!(
+            Some(preview.duration()),
+            state.get_active_timer().unwrap().current_elapsed(now)
+        );
+        assert_eq!(preview.end, now);
+        assert_eq!(preview.comment, "Planning");
+
+        // nothing was committed
+        assert_eq!(state.get_active_timer().unwrap().records_len(), 0);
+    }
+
+    #[test]
+    fn test_start_force_restart() {
+        let start_time = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        let mut cmd = start_command("work", true, false);
+        cmd.comment = Some("Morning".to_owned());
+        start(&mut state, &cmd, &Config::default(), start_time).unwrap();
+
+        cmd.comment = None;
+        assert_eq!(
+            start(&mut state, &cmd, &Config::default(), now).unwrap_err(),
+            AppError::TimerAlreadyRunning
+        );
+
+        cmd.force = true;
+        start(&mut state, &cmd, &Config::default(), now).unwrap();
 
-        let timer = Timer {
-            records: vec![record, record2],
-            current_start: None,
+        let timer = state.get_timer("work").unwrap();
+        assert_eq!(timer.records_len(), 1);
+        assert_eq!(timer.records[0].comment, "");
+        assert_eq!(timer.records[0].duration(), hours(3));
+        assert_eq!(timer.current_start, Some(now));
+        assert_eq!(timer.current_comment, None);
+
+        // with --comment both the stopped and the new record get it
+        cmd.comment = Some("Afternoon".to_owned());
+        start(
+            &mut state,
+            &cmd,
+            &Config::default(),
+            now + chrono::Duration::hours(1),
+        )
+        .unwrap();
+
+        let timer = state.get_timer("work").unwrap();
+        assert_eq!(timer.records[1].comment, "Afternoon");
+        assert_eq!(timer.current_comment.as_deref(), Some("Afternoon"));
+    }
+
+    #[test]
+    fn test_start_force_switch() {
+        let start_time = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        let mut work = start_command("work", true, false);
+        work.comment = Some("Morning".to_owned());
+        start(&mut state, &work, &Config::default(), start_time).unwrap();
+
+        // a missing target timer leaves the running one alone
+        let mut cmd = start_command("hobby", false, false);
+        cmd.force = true;
+        assert_eq!(
+            start(&mut state, &cmd, &Config::default(), now).unwrap_err(),
+            AppError::NoSuchTimer
+        );
+        assert!(state.get_timer("work").unwrap().is_running());
+
+        cmd.create = true;
+        start(&mut state, &cmd, &Config::default(), now).unwrap();
+
+        let work = state.get_timer("work").unwrap();
+        assert!(!work.is_running());
+        assert_eq!(work.records[0].duration(), hours(3));
+        assert_eq!(work.records[0].comment, "");
+        assert!(state.get_timer("hobby").unwrap().is_running());
+        assert_eq!(state.active_timer.as_deref(), Some("hobby"));
+    }
+
+    fn invoice_fixture() -> AppState {
+        let record = |day: u32, start_hour: u32, minutes: i64| {
+            let start = Utc.ymd(2022, 4, day).and_hms(start_hour, 0, 0);
+            TimerRecord::new(
+                start,
+                start + chrono::Duration::minutes(minutes),
+                "".to_owned(),
+            )
         };
 
-        assert_eq!(timer.total_duration(), total_duration);
+        let mut state = AppState::default();
+        state.create_timer("acme");
+        let acme = state.get_timer_mut("acme").unwrap();
+        acme.rate = Some(80.0);
+        acme.records = vec![
+            record(1, 9, 90),
+            record(1, 14, 30),
+            record(4, 9, 45),
+            // not in April
+            TimerRecord::new(
+                Utc.ymd(2022, 3, 15).and_hms(12, 0, 0),
+                Utc.ymd(2022, 3, 15).and_hms(14, 0, 0),
+                "".to_owned(),
+            ),
+        ];
+        let mut rush = record(4, 20, 60);
+        rush.rate_override = Some(120.0);
+        acme.records.push(rush);
+
+        state
     }
 
     #[test]
-    fn test_appstate_set_active_timer_nonexisting() {
+    fn test_compute_invoice() {
+        let state = invoice_fixture();
+        let invoice = compute_invoice(
+            &state,
+            &["acme"],
+            parse_month("2022-04").unwrap(),
+            CurrencyRounding::TwoDecimals,
+        );
+
+        let lines: Vec<(NaiveDate, f64, f64, f64)> = invoice
+            .lines
+            .iter()
+            .map(|line| (line.date, line.hours, line.rate, line.subtotal))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (NaiveDate::from_ymd(2022, 4, 1), 2.0, 80.0, 160.0),
+                (NaiveDate::from_ymd(2022, 4, 4), 0.75, 80.0, 60.0),
+                (NaiveDate::from_ymd(2022, 4, 4), 1.0, 120.0, 120.0),
+            ]
+        );
+        assert_eq!(invoice.total, 340.0);
+    }
+
+    #[test]
+    fn test_render_invoice_csv() {
+        let state = invoice_fixture();
+        let invoice = compute_invoice(
+            &state,
+            &["acme"],
+            parse_month("2022-04").unwrap(),
+            CurrencyRounding::TwoDecimals,
+        );
+        let csv = render_invoice(&invoice, "USD", InvoiceFormat::Csv, Some(NumberLocale::De));
+
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            vec![
+                "date,timer,hours,rate,amount,currency",
+                "2022-04-01,acme,2.00,80.00,160.00,USD",
+                "2022-04-04,acme,0.75,80.00,60.00,USD",
+                "2022-04-04,acme,1.00,120.00,120.00,USD",
+                "total,,,,340.00,USD",
+            ]
+        );
+    }
+
+    fn sort_fixture() -> AppState {
         let mut state = AppState::default();
+        for name in ["beta", "alpha", "gamma"] {
+            state.create_timer(name);
+        }
+
+        // alpha: most time, beta: most records, gamma: most recent
+        state.get_timer_mut("alpha").unwrap().records = vec![record_at(8, 12)];
+        state.get_timer_mut("beta").unwrap().records = vec![record_at(12, 13), record_at(13, 14)];
+        state.get_timer_mut("gamma").unwrap().records = vec![record_at(15, 16)];
+
+        state
+    }
+
+    fn sorted_names(state: &AppState, sort: ListSort, reverse: bool) -> Vec<&str> {
+        sorted_timers(state, sort, reverse)
+            .into_iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_sorted_timers() {
+        let state = sort_fixture();
 
         assert_eq!(
-            state.set_timer_active("something").unwrap_err(),
+            sorted_names(&state, ListSort::Name, false),
+            ["alpha", "beta", "gamma"]
+        );
+        assert_eq!(
+            sorted_names(&state, ListSort::Total, false),
+            ["alpha", "beta", "gamma"]
+        );
+        assert_eq!(
+            sorted_names(&state, ListSort::Records, false),
+            ["beta", "alpha", "gamma"]
+        );
+        assert_eq!(
+            sorted_names(&state, ListSort::Recent, false),
+            ["gamma", "beta", "alpha"]
+        );
+    }
+
+    #[test]
+    fn test_sorted_timers_reverse() {
+        let mut state = sort_fixture();
+
+        assert_eq!(
+            sorted_names(&state, ListSort::Name, true),
+            ["gamma", "beta", "alpha"]
+        );
+        assert_eq!(
+            sorted_names(&state, ListSort::Recent, true),
+            ["alpha", "beta", "gamma"]
+        );
+
+        // a running timer is the most recent one
+        state.get_timer_mut("alpha").unwrap().current_start =
+            Some(Utc.ymd(2022, 4, 2).and_hms(8, 0, 0));
+        assert_eq!(
+            sorted_names(&state, ListSort::Recent, false),
+            ["alpha", "gamma", "beta"]
+        );
+    }
+
+    #[test]
+    fn test_document_schema() {
+        let properties = |document| {
+            let schema = serde_json::to_string(&document_schema(document)).unwrap();
+            let schema: serde_json::Value = serde_json::from_str(&schema).unwrap();
+            let mut properties: Vec<String> = schema["properties"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect();
+            properties.sort();
+            properties
+        };
+
+        assert_eq!(
+            properties(SchemaDocument::State),
+            ["active_timer", "aliases", "last_report_at", "timers"]
+        );
+        assert_eq!(
+            properties(SchemaDocument::Export),
+            ["comment", "end", "rate_override", "start", "tags", "timer"]
+        );
+    }
+
+    #[test]
+    fn test_env_info_json_keys() {
+        let config = Config {
+            currency: Some("USD".to_owned()),
+            ..Config::default()
+        };
+        let state_path = Path::new("/data/mtt/state.json");
+        let config_path = Path::new("/config/mtt/config.json");
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let info = EnvInfo::new(state_path, config_path, &config, 3, false, now);
+
+        let json = serde_json::to_value(&info).unwrap();
+        for key in [
+            "version",
+            "data_dir",
+            "state_path",
+            "config_path",
+            "config_found",
+            "total_records",
+            "timezone",
+            "color",
+            "config",
+        ] {
+            assert!(json.get(key).is_some(), "missing key {}", key);
+        }
+        assert_eq!(json["data_dir"], "/data/mtt");
+        assert_eq!(json["config"]["currency"], "USD");
+
+        let text = render_env(&info).unwrap();
+        assert!(text.contains("state_path:               /data/mtt/state.json\n"));
+        assert!(text.contains("config.currency:          USD\n"));
+        assert!(text.contains("config.prompt_format:     (unset)\n"));
+    }
+
+    #[test]
+    fn test_state_recovers_from_backup() {
+        let dir = std::env::temp_dir().join(format!("mtt-test-{}-recover", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("state.json");
+
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.write_to_file(&path, false).unwrap();
+        assert!(!backup_path_of(&path).exists());
+
+        // the second write keeps the first state as backup
+        state.create_timer("hobby");
+        state.write_to_file(&path, false).unwrap();
+        assert!(backup_path_of(&path).exists());
+
+        std::fs::write(&path, "{\"timers\": {").unwrap();
+        let recovered = AppState::read_from_file(&path, false).unwrap();
+        assert!(recovered.get_timer("work").is_some());
+        assert!(recovered.get_timer("hobby").is_none());
+
+        // a corrupt state is never moved over the backup
+        recovered.write_to_file(&path, false).unwrap();
+        std::fs::write(&path, "garbage").unwrap();
+        assert!(AppState::read_from_file(&path, false).is_ok());
+
+        std::fs::write(backup_path_of(&path), "garbage").unwrap();
+        assert!(AppState::read_from_file(&path, false).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_state_dangling_active_timer() {
+        let path =
+            std::env::temp_dir().join(format!("mtt-test-{}-dangling.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"timers": {"work": {"records": [], "current_start": null}}, "active_timer": "gone"}"#,
+        )
+        .unwrap();
+
+        let state = AppState::read_from_file(&path, false).unwrap();
+        assert_eq!(state.active_timer, None);
+        assert!(state.get_timer("work").is_some());
+
+        let err = AppState::read_from_file(&path, true).err().unwrap();
+        assert_eq!(err.to_string(), "Active timer gone does not exist");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_list_only_running() {
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("hobby");
+        state
+            .get_timer_mut("hobby")
+            .unwrap()
+            .start_timer(now)
+            .unwrap();
+
+        let mut cmd = ListCommand {
+            json: false,
+            json_pretty: false,
+            sort: ListSort::Name,
+            reverse: false,
+            only_running: false,
+            all: true,
+        };
+        let names = |cmd: &ListCommand| -> Vec<String> {
+            listed_timers(&state, cmd)
+                .into_iter()
+                .map(|(name, _)| name.clone())
+                .collect()
+        };
+        assert_eq!(names(&cmd), ["hobby", "work"]);
+
+        cmd.only_running = true;
+        assert_eq!(names(&cmd), ["hobby"]);
+    }
+
+    #[test]
+    fn test_list_hides_empty_timers() {
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.create_timer("fresh");
+        state.get_timer_mut("work").unwrap().records = vec![record_at(9, 10)];
+
+        assert!(state.get_timer("fresh").unwrap().is_empty());
+        assert!(!state.get_timer("work").unwrap().is_empty());
+
+        let mut cmd = ListCommand {
+            json: false,
+            json_pretty: false,
+            sort: ListSort::Name,
+            reverse: false,
+            only_running: false,
+            all: false,
+        };
+        let names = |cmd: &ListCommand| -> Vec<String> {
+            listed_timers(&state, cmd)
+                .into_iter()
+                .map(|(name, _)| name.clone())
+                .collect()
+        };
+        assert_eq!(names(&cmd), ["work"]);
+
+        cmd.all = true;
+        assert_eq!(names(&cmd), ["fresh", "work"]);
+    }
+
+    #[test]
+    fn test_pinned_timers_first() {
+        let mut state = sort_fixture();
+
+        pin(
+            &mut state,
+            &PinCommand {
+                timer_name: "gamma".to_owned(),
+            },
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            sorted_names(&state, ListSort::Name, false),
+            ["gamma", "alpha", "beta"]
+        );
+        assert_eq!(
+            sorted_names(&state, ListSort::Total, false),
+            ["gamma", "alpha", "beta"]
+        );
+        assert_eq!(
+            sorted_names(&state, ListSort::Name, true),
+            ["gamma", "beta", "alpha"]
+        );
+
+        pin(
+            &mut state,
+            &PinCommand {
+                timer_name: "gamma".to_owned(),
+            },
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            sorted_names(&state, ListSort::Name, false),
+            ["alpha", "beta", "gamma"]
+        );
+
+        assert_eq!(
+            pin(
+                &mut state,
+                &PinCommand {
+                    timer_name: "delta".to_owned()
+                },
+                true
+            )
+            .unwrap_err(),
             AppError::NoSuchTimer
         );
     }
 
     #[test]
-    fn test_appstate_create_timer() {
+    fn test_get_capped_duration_string() {
+        let duration = Duration::from_secs(2 * 86400 + 3 * 3600 + 4 * 60 + 5);
+
+        assert_eq!(get_capped_duration_string(&duration, Some(1)), "2days");
+        assert_eq!(get_capped_duration_string(&duration, Some(2)), "2days 3h");
+        assert_eq!(
+            get_capped_duration_string(&duration, Some(4)),
+            "2days 3h 4m 5s"
+        );
+        assert_eq!(
+            get_capped_duration_string(&duration, Some(10)),
+            "2days 3h 4m 5s"
+        );
+        assert_eq!(
+            get_capped_duration_string(&duration, None),
+            "2days 3h 4m 5s"
+        );
+    }
+
+    #[test]
+    fn test_backup_restore() {
+        let dir = std::env::temp_dir().join(format!("mtt-test-{}-backups", std::process::id()));
+        let now = Utc.ymd(2022, 4, 1).and_hms(12, 0, 0);
         let mut state = AppState::default();
-        let timer_name = "timer name";
+        state.create_timer("work");
+        state.get_timer_mut("work").unwrap().records = vec![record_at(8, 9), record_at(10, 11)];
 
-        state.create_timer(timer_name).unwrap();
+        let path = backup(&state, &dir, false, now).unwrap();
+        assert_eq!(path, dir.join("state-20220401T120000Z.json"));
 
-        // can't create a second timer with the same name
-        assert!(state.create_timer(timer_name).is_none());
+        // a second backup within the same second doesn't overwrite the first
+        let second = backup(&AppState::default(), &dir, false, now).unwrap();
+        assert_eq!(second, dir.join("state-20220401T120000Z-1.json"));
+        assert_eq!(AppState::read_backup(&path).unwrap().record_count(), 2);
+
+        state.get_timer_mut("work").unwrap().records.clear();
+        state.create_timer("hobby");
+
+        let cmd = RestoreCommand { path, yes: false };
+        assert!(restore(&mut state, &cmd, |_| false).is_err());
+        assert_eq!(state.record_count(), 0);
+
+        restore(&mut state, &cmd, |_| true).unwrap();
+        assert_eq!(state.record_count(), 2);
+        assert!(state.get_timer("hobby").is_none());
+
+        // a broken backup leaves the state alone
+        std::fs::write(&cmd.path, "{").unwrap();
+        state.create_timer("hobby");
+        assert!(restore(&mut state, &cmd, |_| true).is_err());
+        assert!(state.get_timer("hobby").is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn test_appstate_get_timer() {
+    fn test_write_pretty_state() {
+        let path =
+            std::env::temp_dir().join(format!("mtt-test-{}-pretty.json", std::process::id()));
         let mut state = AppState::default();
-        let timer_name = "timer name";
+        state.create_timer("work");
 
-        state.create_timer(timer_name).unwrap();
+        state.write_to_file(&path, false).unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains('\n'));
 
-        let timer1 = state.get_timer(timer_name).unwrap();
+        state.write_to_file(&path, true).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("{\n  \"timers\": {\n    \"work\": {"));
+        assert!(AppState::read_from_file(&path, true)
+            .unwrap()
+            .get_timer("work")
+            .is_some());
 
-        let timer2 = state.get_timer(timer_name).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(backup_path_of(&path)).unwrap();
+    }
 
-        assert!(std::ptr::eq(timer1, timer2));
+    #[test]
+    fn test_with_state_keeps_external_edits() {
+        let path =
+            std::env::temp_dir().join(format!("mtt-test-{}-interleaved.json", std::process::id()));
+        let mut state = AppState::default();
+        state.create_timer("work");
+        state.write_to_file(&path, false).unwrap();
+
+        // another mtt process holds the lock while this one starts up, and
+        // changes the state before releasing it
+        let lock = StateLock::acquire(&path, 1).unwrap();
+        let (started, waiting) = std::sync::mpsc::channel();
+        let writer = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                started.send(()).unwrap();
+                with_state(&path, false, true, false, LOCK_ATTEMPTS, |state| {
+                    state.create_timer("other");
+                    Ok(())
+                })
+                .map_err(|err| err.to_string())
+            })
+        };
+        waiting.recv().unwrap();
+        std::thread::sleep(LOCK_RETRY_DELAY * 2);
+
+        let mut external = AppState::read_from_file(&path, false).unwrap();
+        external.create_timer("hobby");
+        external.write_to_file(&path, false).unwrap();
+        drop(lock);
+        writer.join().unwrap().unwrap();
+
+        let state = AppState::read_from_file(&path, false).unwrap();
+        assert_eq!(state.timer_names_sorted(), ["hobby", "other", "work"]);
+
+        // while another process holds the lock, writing commands give up
+        let lock = StateLock::acquire(&path, 1).unwrap();
+        let result = with_state(&path, false, true, false, 1, |state| {
+            state.create_timer("blocked");
+            Ok(())
+        });
+        assert!(result.unwrap_err().to_string().contains("locked"));
+        // reading ones don't need the lock
+        assert!(with_state(&path, false, false, false, 1, |_| Ok(())).is_ok());
+        drop(lock);
+
+        assert!(with_state(&path, false, true, false, 1, |_| Ok(())).is_ok());
+        assert!(AppState::read_from_file(&path, false)
+            .unwrap()
+            .get_timer("blocked")
+            .is_none());
+
+        // the lock of a process that is gone doesn't block, one of a running process does
+        let lock_path = path.with_file_name(format!(
+            "{}.lock",
+            path.file_name().unwrap().to_string_lossy()
+        ));
+        std::fs::write(&lock_path, u32::MAX.to_string()).unwrap();
+        assert!(with_state(&path, false, true, false, 1, |_| Ok(())).is_ok());
+        assert!(!lock_path.exists());
+        std::fs::write(&lock_path, std::process::id().to_string()).unwrap();
+        let err = with_state(&path, false, true, false, 1, |_| Ok(())).unwrap_err();
+        assert!(err.to_string().contains(&lock_path.display().to_string()));
+        std::fs::remove_file(&lock_path).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(backup_path_of(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_statefile_path_unwritable_data_dir() {
+        let blocker = std::env::temp_dir().join(format!("mtt-test-{}-blocker", std::process::id()));
+        File::create(&blocker).unwrap();
+
+        // a regular file in place of a parent directory can't be turned into a directory
+        let data_dir = blocker.join("data");
+        let err = statefile_path_in(&data_dir).unwrap_err();
+
+        assert!(matches!(err, AppError::CannotCreateDataDir(ref path, _) if path == &data_dir));
+        assert!(err.to_string().starts_with("cannot create data dir at "));
+
+        std::fs::remove_file(&blocker).unwrap();
+    }
+
+    #[test]
+    fn test_timer_stats_min_max() {
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+        let start = Utc.ymd(2022, 4, 1).and_hms(9, 0, 0);
+        let records: Vec<TimerRecord> = [1, 30, 40, 50, 600]
+            .iter()
+            .map(|&length| {
+                TimerRecord::new(
+                    start,
+                    start + chrono::Duration::minutes(length),
+                    "".to_owned(),
+                )
+            })
+            .collect();
+
+        let stats = TimerStats::new(durations_between(&records, None, None)).unwrap();
+        assert_eq!(stats.count, 5);
+        assert_eq!(stats.mean, Duration::from_secs(721 * 60 / 5));
+        assert_eq!(stats.median, minutes(40));
+
+        // without the micro- and mega-sessions
+        let stats = TimerStats::new(durations_between(
+            &records,
+            Some(minutes(5)),
+            Some(minutes(120)),
+        ))
+        .unwrap();
+        assert_eq!(
+            stats,
+            TimerStats {
+                count: 3,
+                total: minutes(120),
+                mean: minutes(40),
+                median: minutes(40),
+                shortest: minutes(30),
+                longest: minutes(50),
+            }
+        );
+
+        // an even number of records has the mean of the middle two as median
+        let stats = TimerStats::new(durations_between(&records, Some(minutes(5)), None)).unwrap();
+        assert_eq!(stats.median, minutes(45));
+
+        assert_eq!(
+            TimerStats::new(durations_between(&records, Some(hours(20)), None)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_duration_as() {
+        let duration = Duration::from_secs(5400);
+
+        assert_eq!(duration_as(&duration, DurationUnit::Seconds), 5400.0);
+        assert_eq!(duration_as(&duration, DurationUnit::Minutes), 90.0);
+        assert_eq!(duration_as(&duration, DurationUnit::Hours), 1.5);
+
+        assert_eq!(format_duration_as(&duration, DurationUnit::Minutes), "90");
+        assert_eq!(format_duration_as(&duration, DurationUnit::Hours), "1.5");
+        assert_eq!(
+            format_duration_as(&Duration::from_secs(100), DurationUnit::Hours),
+            "0.03"
+        );
+        assert_eq!(
+            format_duration_as(&Duration::ZERO, DurationUnit::Seconds),
+            "0"
+        );
+    }
+
+    #[test]
+    fn test_format_decimal_hours() {
+        let duration = Duration::from_secs(6480); // 1.8h
+
+        assert_eq!(format_decimal_hours(&duration, 2, false), "1.80");
+        assert_eq!(format_decimal_hours(&duration, 1, false), "1.8");
+        assert_eq!(format_decimal_hours(&duration, 2, true), "1.75");
+        assert_eq!(
+            format_decimal_hours(&Duration::from_secs(6120), 2, true), // 1.7h
+            "1.75"
+        );
+        assert_eq!(format_decimal_hours(&hours(2), 0, true), "2");
+    }
+
+    #[test]
+    fn test_duration_between() {
+        let now = Utc.ymd(2022, 4, 3).and_hms(12, 0, 0);
+
+        assert_eq!(
+            duration_between("2022-04-03T09:15:00Z", "2022-04-03T11:45:30Z", now),
+            Ok(Duration::from_secs(2 * 3600 + 30 * 60 + 30))
+        );
+        assert_eq!(
+            duration_between("1h ago", "2022-04-03T12:00:00Z", now),
+            Ok(Duration::from_secs(3600))
+        );
+    }
+
+    #[test]
+    fn test_duration_between_reversed() {
+        let now = Utc.ymd(2022, 4, 3).and_hms(12, 0, 0);
+
+        assert_eq!(
+            duration_between("2022-04-03T11:00:00Z", "2022-04-03T09:00:00Z", now),
+            Err(AppError::EndBeforeStart)
+        );
+        assert!(matches!(
+            duration_between("soon", "2022-04-03T09:00:00Z", now),
+            Err(AppError::BadTime(_))
+        ));
+    }
+
+    #[test]
+    fn test_listed_records_today() {
+        let now = Local
+            .ymd(2022, 4, 13)
+            .and_hms(15, 30, 0)
+            .with_timezone(&Utc);
+        let local = |day, hour| {
+            Local
+                .ymd(2022, 4, day)
+                .and_hms(hour, 0, 0)
+                .with_timezone(&Utc)
+        };
+        let timer = Timer {
+            records: vec![
+                TimerRecord::new(local(12, 23), local(13, 1), String::new()),
+                TimerRecord::new(local(13, 0), local(13, 1), String::new()),
+                TimerRecord::new(local(13, 9), local(13, 12), String::new()),
+                TimerRecord::new(local(13, 14), local(13, 15), String::new()),
+                TimerRecord::new(local(14, 0), local(14, 1), String::new()),
+            ],
+            ..Timer::default()
+        };
+        let mut cmd = RecordsCommand {
+            timer_name: None,
+            relative: false,
+            wrap_comments: None,
+            today: true,
+            yesterday: false,
+            week: false,
+            limit: None,
+            reverse: false,
+            range: RangeOptions {
+                since: None,
+                until: None,
+                range: None,
+                min_duration: None,
+            },
+        };
+        let indices = |cmd: &RecordsCommand| -> Vec<usize> {
+            listed_records(&timer, cmd, StartOfWeek::Monday, now)
+                .unwrap()
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect()
+        };
+
+        assert_eq!(indices(&cmd), [1, 2, 3]);
+
+        cmd.limit = Some(2);
+        cmd.reverse = true;
+        assert_eq!(indices(&cmd), [3, 2]);
+
+        cmd.today = false;
+        cmd.yesterday = true;
+        cmd.limit = None;
+        assert_eq!(indices(&cmd), [0]);
+
+        cmd.yesterday = false;
+        cmd.week = true;
+        assert_eq!(indices(&cmd), [4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_resolve_range() {
+        // a Wednesday
+        let now = Local
+            .ymd(2022, 4, 13)
+            .and_hms(15, 30, 0)
+            .with_timezone(&Utc);
+        let local = |month, day| {
+            Local
+                .ymd(2022, month, day)
+                .and_hms(0, 0, 0)
+                .with_timezone(&Utc)
+        };
+
+        assert_eq!(
+            resolve_range(RangeKeyword::Today, StartOfWeek::Monday, now),
+            (local(4, 13), local(4, 14))
+        );
+        assert_eq!(
+            resolve_range(RangeKeyword::Yesterday, StartOfWeek::Monday, now),
+            (local(4, 12), local(4, 13))
+        );
+        assert_eq!(
+            resolve_range(RangeKeyword::Week, StartOfWeek::Monday, now),
+            (local(4, 11), local(4, 18))
+        );
+        assert_eq!(
+            resolve_range(RangeKeyword::Week, StartOfWeek::Sunday, now),
+            (local(4, 10), local(4, 17))
+        );
+        assert_eq!(
+            resolve_range(RangeKeyword::Month, StartOfWeek::Monday, now),
+            (local(4, 1), local(5, 1))
+        );
+
+        let december = Local
+            .ymd(2022, 12, 31)
+            .and_hms(12, 0, 0)
+            .with_timezone(&Utc);
+        assert_eq!(
+            resolve_range(RangeKeyword::Month, StartOfWeek::Monday, december).1,
+            Local.ymd(2023, 1, 1).and_hms(0, 0, 0).with_timezone(&Utc)
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let now = Utc.ymd(2022, 4, 3).and_hms(12, 0, 0);
+
+        assert_eq!(
+            parse_time("2022-04-03T10:30:00Z", now).unwrap(),
+            Utc.ymd(2022, 4, 3).and_hms(10, 30, 0)
+        );
+        assert_eq!(
+            parse_time("10m ago", now).unwrap(),
+            Utc.ymd(2022, 4, 3).and_hms(11, 50, 0)
+        );
+        assert_eq!(
+            parse_time("yesterday-ish", now).unwrap_err(),
+            AppError::BadTime("yesterday-ish".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_bad_time_message() {
+        let now = Utc.ymd(2022, 4, 3).and_hms(12, 0, 0);
+        let message = parse_time("half past nine", now).unwrap_err().to_string();
+
+        assert!(message.contains("\"half past nine\""), "{}", message);
+        assert!(message.contains("\"09:30\""), "{}", message);
+        assert!(message.contains("\"10m ago\""), "{}", message);
+        assert!(message.contains("\"2022-04-01 09:30\""), "{}", message);
     }
 }